@@ -0,0 +1,337 @@
+//! The demo: animated boxes, or a mesh, scene or voxel model given through the environment,
+//! anti-aliased with MSAA and FXAA.
+
+use anima::animation::{Animation, Clip, Easing, Keyframe, LoopMode, Track};
+use anima::fxaa::{FXAAPass, FxaaSettings};
+use anima::graph::{PassDesc, RenderGraph, SizePolicy, TextureDesc, BACKBUFFER};
+use anima::postprocess::PostProcessChain;
+#[cfg(feature = "hot-reload")]
+use anima::shaders;
+use anima::{box_renderer, framework, mesh, msaa, scene, vox, AnimaError, BoxRenderer};
+use cgmath::Rotation3;
+
+struct Example {
+    box_renderer: BoxRenderer,
+    post: PostProcessChain,
+    graph: RenderGraph,
+    animation: Animation,
+    /// Shown instead of the demo animation, from `ANIMA_SCENE`.
+    scene: Option<scene::ScenePlayer>,
+    /// Whether the instances come from `ANIMA_VOX`, instead of the demo animation.
+    voxels: bool,
+    adapter_info: Option<wgpu::AdapterInfo>,
+    /// Applied on the next frame, as switching needs the device.
+    requested_sample_count: Option<u32>,
+    #[cfg(feature = "hot-reload")]
+    shaders: shaders::ShaderLoader,
+}
+
+impl Example {
+    /// A box spinning around the vertical axis, fading from red to orange.
+    fn demo_animation() -> Animation {
+        let turn = |degrees: f32| cgmath::Quaternion::from_angle_z(cgmath::Deg(degrees));
+        let clip = Clip {
+            rotation: Some(Track::new(vec![
+                Keyframe::new(0., turn(0.), Easing::Linear),
+                Keyframe::new(2., turn(180.), Easing::Linear),
+                Keyframe::new(4., turn(360.), Easing::Linear),
+            ])),
+            color: Some(Track::new(vec![
+                Keyframe::new(0., [1., 0., 0., 1.], Easing::EaseInOut),
+                Keyframe::new(2., [1., 0.5, 0., 1.], Easing::EaseInOut),
+                Keyframe::new(4., [1., 0., 0., 1.], Easing::EaseInOut),
+            ])),
+            ..Clip::default()
+        };
+        Animation::new(clip, LoopMode::Loop)
+    }
+
+    /// The MSAA sample count set with `ANIMA_MSAA`, if any.
+    fn sample_count_from_env() -> Option<u32> {
+        let value = std::env::var("ANIMA_MSAA").ok()?;
+        match value.parse() {
+            Ok(sample_count) => Some(sample_count),
+            Err(_) => {
+                log::warn!("Ignoring invalid ANIMA_MSAA: {}", value);
+                None
+            }
+        }
+    }
+
+    /// Loads `ANIMA_VOX` into the renderer, as boxes or, with `ANIMA_VOX_MODE=mesh`, a mesh.
+    /// Returns whether a file was loaded.
+    fn load_voxels(box_renderer: &mut BoxRenderer, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let path = match std::env::var_os("ANIMA_VOX") {
+            Some(path) => std::path::PathBuf::from(path),
+            None => return false,
+        };
+        let file = match vox::VoxFile::load(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                log::error!("Cannot load {}: {}", path.display(), err);
+                return false;
+            }
+        };
+        let mode = match std::env::var("ANIMA_VOX_MODE").ok().as_deref() {
+            Some("mesh") => vox::VoxMode::Mesh,
+            Some("boxes") | None => vox::VoxMode::Boxes,
+            Some(mode) => {
+                log::warn!("Ignoring unknown ANIMA_VOX_MODE: {}", mode);
+                vox::VoxMode::Boxes
+            }
+        };
+
+        let voxel_size = file.fitting_voxel_size();
+        match mode {
+            vox::VoxMode::Boxes => box_renderer.set_instances(&file.box_instances(voxel_size), device, queue),
+            vox::VoxMode::Mesh => {
+                box_renderer.set_mesh(&file.mesh(voxel_size), device);
+                let instance = box_renderer::BoxInstance::new(cgmath::SquareMatrix::identity(), [1.; 4]);
+                box_renderer.set_instances(&[instance], device, queue);
+            }
+        }
+        true
+    }
+
+    /// The boxes are drawn into "scene", which goes through post-processing on its way
+    /// to the backbuffer. Without any post-processing, they're drawn there directly.
+    fn create_graph(format: wgpu::TextureFormat, width: u32, height: u32) -> RenderGraph {
+        let full = TextureDesc::new(format, SizePolicy::Full);
+        let mut graph = RenderGraph::new(format, width, height);
+        graph.add_pass(PassDesc::new("boxes").output("scene", full));
+        graph.add_pass(PassDesc::new("post").input("scene").output(BACKBUFFER, full));
+        graph
+    }
+
+    fn log_anti_aliasing(&self) {
+        let msaa = match self.box_renderer.sample_count() {
+            1 => "no MSAA".to_string(),
+            sample_count => format!("{}x MSAA", sample_count),
+        };
+        let fxaa = if self.post.is_enabled("fxaa") { "FXAA" } else { "no FXAA" };
+        log::info!("Anti-aliasing: {}, {}", msaa, fxaa);
+    }
+}
+
+impl framework::Example for Example {
+
+    fn init(
+        sc_desc: &wgpu::SwapChainDescriptor,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self, AnimaError> {
+        let width = sc_desc.width;
+        let height = sc_desc.height;
+        let format = sc_desc.format;
+
+        let mut post = PostProcessChain::new(format, width, height);
+        let fxaa = FXAAPass::new(format, width, height, FxaaSettings::default(), device, queue)?;
+        post.push(Box::new(fxaa));
+
+        let mut graph = Example::create_graph(format, width, height);
+        graph.compile(device)?;
+
+        let mut box_renderer = BoxRenderer::new(format, width, height, device, queue)?;
+        if let Some(path) = std::env::var_os("ANIMA_MESH") {
+            let path = std::path::Path::new(&path);
+            match mesh::Mesh::load(path) {
+                Ok(mesh) => box_renderer.set_mesh(&mesh, device),
+                Err(err) => log::error!("Cannot load {}: {}", path.display(), err),
+            }
+        }
+
+        let voxels = Example::load_voxels(&mut box_renderer, device, queue);
+        let scene = std::env::var_os("ANIMA_SCENE").and_then(|path| {
+            let path = std::path::Path::new(&path);
+            match scene::Scene::load(path) {
+                Ok(scene) => {
+                    let player = scene::ScenePlayer::new(scene, &mut box_renderer, device, queue);
+                    player.apply_camera(box_renderer.camera_mut());
+                    Some(player)
+                }
+                Err(err) => {
+                    log::error!("Cannot load {}: {}", path.display(), err);
+                    None
+                }
+            }
+        });
+
+        Ok(Example {
+            box_renderer,
+            post,
+            graph,
+            animation: Example::demo_animation(),
+            scene,
+            voxels,
+            adapter_info: None,
+            requested_sample_count: Example::sample_count_from_env(),
+            #[cfg(feature = "hot-reload")]
+            shaders: shaders::ShaderLoader::new().map_err(AnimaError::ShaderCompile)?,
+        })
+    }
+
+    fn adapter_info(&mut self, info: &wgpu::AdapterInfo) {
+        self.adapter_info = Some(info.clone());
+    }
+
+    /// M cycles through the supported MSAA sample counts, F toggles FXAA,
+    /// C cycles through the scene's animation clips.
+    fn update(&mut self, event: winit::event::WindowEvent) {
+        use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+        if self.box_renderer.camera_mut().handle_event(&event) {
+            return;
+        }
+        if let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    virtual_keycode: Some(key),
+                    state: ElementState::Pressed,
+                    ..
+                },
+            ..
+        } = event
+        {
+            match key {
+                VirtualKeyCode::M => {
+                    if let Some(info) = &self.adapter_info {
+                        let current = self.box_renderer.sample_count();
+                        let format = self.box_renderer.format();
+                        self.requested_sample_count = Some(msaa::next_sample_count(current, info, format));
+                    }
+                }
+                VirtualKeyCode::F => {
+                    let enabled = self.post.is_enabled("fxaa");
+                    self.post.set_enabled("fxaa", !enabled);
+                    self.graph.set_enabled("post", self.post.is_active());
+                    self.log_anti_aliasing();
+                }
+                VirtualKeyCode::C => {
+                    if let Some(player) = &mut self.scene {
+                        player.next_clip();
+                        let name = player
+                            .clip()
+                            .map(|clip| player.scene().clips[clip].name.clone().unwrap_or_else(|| clip.to_string()));
+                        log::info!("Playing clip: {}", name.as_deref().unwrap_or("none"));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn tick(&mut self, dt: std::time::Duration) {
+        match &mut self.scene {
+            Some(player) => player.advance(dt),
+            None => self.animation.advance(dt),
+        }
+    }
+
+    fn wants_redraw(&self) -> bool {
+        match &self.scene {
+            Some(player) => player.is_animated(),
+            None => !self.voxels && self.animation.playing,
+        }
+    }
+
+    fn resize(
+        &mut self,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let width = sc_desc.width;
+        let height = sc_desc.height;
+        if width == 0 || height == 0 {
+            // minimized, keep the old targets until the window comes back
+            return;
+        }
+        self.box_renderer.resize(width, height, device, queue);
+        self.graph.resize(width, height, device);
+        self.post.resize(width, height, device, queue);
+    }
+
+    fn render(
+        &mut self,
+        frame: &wgpu::SwapChainTexture,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        spawner: &impl futures::task::LocalSpawn,
+    ) {
+        if let Err(err) = self.render_view(&frame.view, device, queue, spawner) {
+            log::error!("Cannot render: {}", err);
+        }
+    }
+
+    fn render_view(
+        &mut self,
+        frame: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _spawner: &impl futures::task::LocalSpawn,
+    ) -> Result<(), AnimaError> {
+        // only polled here, so with on-demand pacing edits wait for the next input
+        #[cfg(feature = "hot-reload")]
+        {
+            let box_renderer = &mut self.box_renderer;
+            self.shaders.reload_if_changed("shader.vert", "shader.frag", |vs, fs| {
+                box_renderer.set_shaders(device, vs, fs)
+            });
+            if let Some(fxaa) = self.post.pass_mut("fxaa") {
+                self.shaders.reload_if_changed("fxaa.vert", "fxaa.frag", |vs, fs| {
+                    fxaa.set_shaders(device, vs, fs)
+                });
+            }
+        }
+
+        if let (Some(sample_count), Some(info)) = (self.requested_sample_count.take(), &self.adapter_info) {
+            match self.box_renderer.set_sample_count(sample_count, info, device) {
+                Ok(()) => self.log_anti_aliasing(),
+                Err(err) => log::error!("Cannot switch MSAA: {}", err),
+            }
+        }
+
+        match &self.scene {
+            Some(player) => player.update(&mut self.box_renderer, device, queue),
+            None if self.voxels => {}
+            None => {
+                let instances = [self.animation.pose().box_instance()];
+                self.box_renderer.set_instances(&instances, device, queue);
+            }
+        }
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let post_allocations = self.post.allocations();
+        let Example {
+            box_renderer,
+            post,
+            graph,
+            ..
+        } = self;
+        let result = graph.execute(frame, device, &mut encoder, |pass, resources, encoder| {
+            match (pass, resources.view("scene"), resources.view(BACKBUFFER)) {
+                ("boxes", Some(scene), _) => box_renderer.render(scene, device, queue, encoder),
+                ("post", Some(scene), Some(backbuffer)) => {
+                    post.render_from(backbuffer, scene, resources.generation(), device, queue, encoder)
+                }
+                _ => log::error!("Pass {} is missing a texture, or unknown", pass),
+            }
+        });
+        // submit whatever was recorded before a failure, so the encoder isn't left open
+        queue.submit(Some(encoder.finish()));
+        result?;
+        if self.post.allocations() != post_allocations {
+            log::debug!("Post-processing allocated {} GPU objects so far", self.post.allocations());
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    env_logger::init();
+    if let Err(err) = framework::run::<Example>("cube") {
+        log::error!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,242 @@
+//! Picking the adapter to run on, from the environment or the command line.
+
+use crate::error::AnimaError;
+
+/// Which adapter `framework::run` and `headless::setup` use.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdapterSelection {
+    /// Backends to look for adapters on.
+    pub backends: wgpu::BackendBit,
+    pub power_preference: wgpu::PowerPreference,
+    /// Picks the first adapter whose name contains this, ignoring case,
+    /// instead of letting wgpu choose.
+    pub name: Option<String>,
+    /// Logs the adapters, at the info level, instead of running.
+    pub list: bool,
+}
+
+impl Default for AdapterSelection {
+    fn default() -> Self {
+        AdapterSelection {
+            backends: wgpu::BackendBit::PRIMARY,
+            power_preference: wgpu::PowerPreference::Default,
+            name: None,
+            list: false,
+        }
+    }
+}
+
+/// Comma separated backend names, like "vulkan,gl".
+pub fn parse_backends(value: &str) -> Option<wgpu::BackendBit> {
+    let mut backends = wgpu::BackendBit::empty();
+    for name in value.split(',') {
+        backends |= match name.trim().to_lowercase().as_str() {
+            "vulkan" | "vk" => wgpu::BackendBit::VULKAN,
+            "metal" => wgpu::BackendBit::METAL,
+            "dx12" | "d3d12" => wgpu::BackendBit::DX12,
+            "dx11" | "d3d11" => wgpu::BackendBit::DX11,
+            "gl" | "opengl" => wgpu::BackendBit::GL,
+            "webgpu" => wgpu::BackendBit::BROWSER_WEBGPU,
+            "primary" => wgpu::BackendBit::PRIMARY,
+            "secondary" => wgpu::BackendBit::SECONDARY,
+            "all" => wgpu::BackendBit::all(),
+            _ => return None,
+        };
+    }
+    Some(backends)
+}
+
+pub fn parse_power_preference(value: &str) -> Option<wgpu::PowerPreference> {
+    match value.to_lowercase().as_str() {
+        "default" => Some(wgpu::PowerPreference::Default),
+        "low" | "low-power" => Some(wgpu::PowerPreference::LowPower),
+        "high" | "high-performance" => Some(wgpu::PowerPreference::HighPerformance),
+        _ => None,
+    }
+}
+
+impl AdapterSelection {
+    fn set_backends(&mut self, value: &str, source: &str) {
+        match parse_backends(value) {
+            Some(backends) => self.backends = backends,
+            None => log::warn!("Ignoring unknown {}: {}", source, value),
+        }
+    }
+
+    fn set_power_preference(&mut self, value: &str, source: &str) {
+        match parse_power_preference(value) {
+            Some(power_preference) => self.power_preference = power_preference,
+            None => log::warn!("Ignoring unknown {}: {}", source, value),
+        }
+    }
+
+    /// Applies the `ANIMA_BACKEND`, `ANIMA_POWER` (default, low, high), `ANIMA_ADAPTER`
+    /// and `ANIMA_LIST_ADAPTERS` environment variables.
+    pub fn with_env_overrides(mut self) -> Self {
+        if let Ok(backends) = std::env::var("ANIMA_BACKEND") {
+            self.set_backends(&backends, "ANIMA_BACKEND");
+        }
+        if let Ok(power_preference) = std::env::var("ANIMA_POWER") {
+            self.set_power_preference(&power_preference, "ANIMA_POWER");
+        }
+        if let Ok(name) = std::env::var("ANIMA_ADAPTER") {
+            self.name = Some(name);
+        }
+        if std::env::var_os("ANIMA_LIST_ADAPTERS").is_some() {
+            self.list = true;
+        }
+        self
+    }
+
+    /// Applies the `--backend`, `--power`, `--adapter` and `--list-adapters` flags,
+    /// which take precedence over the environment. Other arguments are ignored.
+    pub fn with_args(mut self, args: impl IntoIterator<Item = String>) -> Self {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.find('=') {
+                Some(index) => (arg[..index].to_string(), Some(arg[index + 1..].to_string())),
+                None => (arg.clone(), None),
+            };
+            if flag == "--list-adapters" {
+                self.list = true;
+                continue;
+            }
+            if !["--backend", "--power", "--adapter"].contains(&flag.as_str()) {
+                continue;
+            }
+            let value = match inline_value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => {
+                    log::warn!("Ignoring {} without a value", flag);
+                    continue;
+                }
+            };
+            match flag.as_str() {
+                "--backend" => self.set_backends(&value, "--backend"),
+                "--power" => self.set_power_preference(&value, "--power"),
+                _ => self.name = Some(value),
+            }
+        }
+        self
+    }
+}
+
+pub fn describe(info: &wgpu::AdapterInfo) -> String {
+    format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type)
+}
+
+/// Describes the adapters on `backends` with their features, one per line.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list_adapters(instance: &wgpu::Instance, backends: wgpu::BackendBit) -> Vec<String> {
+    instance
+        .enumerate_adapters(backends)
+        .enumerate()
+        .map(|(index, adapter)| {
+            let info = adapter.get_info();
+            format!("Adapter {}: {}, features: {:?}", index, describe(&info), adapter.features())
+        })
+        .collect()
+}
+
+/// The adapters wgpu picks for `surface`, for each power preference.
+///
+/// wgpu can't be asked whether a given adapter can present to a surface,
+/// so these are the only ones known to.
+#[cfg(not(target_arch = "wasm32"))]
+async fn surface_adapters(instance: &wgpu::Instance, surface: &wgpu::Surface) -> Vec<wgpu::AdapterInfo> {
+    let mut infos = Vec::new();
+    for &power_preference in &[wgpu::PowerPreference::LowPower, wgpu::PowerPreference::HighPerformance] {
+        let options = wgpu::RequestAdapterOptions {
+            power_preference,
+            compatible_surface: Some(surface),
+        };
+        if let Some(adapter) = instance.request_adapter(&options).await {
+            infos.push(adapter.get_info());
+        }
+    }
+    infos
+}
+
+/// Finds the adapter to use. When a name is given along with `surface`, matching adapters
+/// known to present to it come first. Others are used with a warning, as they may not.
+pub async fn request_adapter(
+    instance: &wgpu::Instance,
+    selection: &AdapterSelection,
+    surface: Option<&wgpu::Surface>,
+) -> Result<wgpu::Adapter, AnimaError> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        if let Some(name) = &selection.name {
+            let lowercase = name.to_lowercase();
+            let mut matching: Vec<wgpu::Adapter> = instance
+                .enumerate_adapters(selection.backends)
+                .filter(|adapter| adapter.get_info().name.to_lowercase().contains(&lowercase))
+                .collect();
+            if matching.is_empty() {
+                return Err(AnimaError::AdapterNotFound(name.clone()));
+            }
+            let surface = match surface {
+                Some(surface) => surface,
+                None => return Ok(matching.remove(0)),
+            };
+            let compatible = surface_adapters(instance, surface).await;
+            let index = match matching
+                .iter()
+                .position(|adapter| compatible.contains(&adapter.get_info()))
+            {
+                Some(index) => index,
+                None => {
+                    log::warn!(
+                        "Adapter {} may not be able to present to the window",
+                        describe(&matching[0].get_info())
+                    );
+                    0
+                }
+            };
+            return Ok(matching.remove(index));
+        }
+    }
+
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: selection.power_preference,
+            compatible_surface: surface,
+        })
+        .await
+        .ok_or(AnimaError::NoAdapter)
+}
+
+/// Logs the chosen adapter, and the device's features and limits.
+pub fn report(adapter: &wgpu::Adapter, device: &wgpu::Device) {
+    log::info!("Using adapter {}", describe(&adapter.get_info()));
+    log::info!("Enabled features: {:?}", device.features());
+    log::info!("Limits: {:?}", device.limits());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_names() {
+        assert_eq!(
+            parse_backends("Vulkan, gl"),
+            Some(wgpu::BackendBit::VULKAN | wgpu::BackendBit::GL)
+        );
+        assert_eq!(parse_backends("glide"), None);
+    }
+
+    #[test]
+    fn args_override() {
+        let selection = AdapterSelection {
+            name: Some("intel".to_string()),
+            ..AdapterSelection::default()
+        };
+        let args = ["--backend=dx12", "--power", "high", "--adapter", "NVIDIA", "scene.gltf"];
+        let selection = selection.with_args(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(selection.backends, wgpu::BackendBit::DX12);
+        assert_eq!(selection.power_preference, wgpu::PowerPreference::HighPerformance);
+        assert_eq!(selection.name.as_deref(), Some("NVIDIA"));
+        assert!(!selection.list);
+    }
+}
@@ -0,0 +1,373 @@
+//! Keyframe animation of box transforms and colors.
+
+use crate::box_renderer::BoxInstance;
+use cgmath::{InnerSpace, Matrix4, One, Quaternion, Vector3, VectorSpace};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum AnimationError {
+    /// A keyframe time is infinite or NaN, so keyframes can't be ordered.
+    NonFiniteTime(f32),
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimationError::NonFiniteTime(time) => write!(f, "invalid keyframe time: {}", time),
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {}
+
+/// Shapes the progression between two keyframes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    /// Holds the value of the first keyframe until the next one.
+    Step,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps a linear progression in [0, 1] to an eased one.
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Step => 0.,
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => {
+                let u = 1. - t;
+                1. - u * u * u
+            }
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+/// Values which can be blended between keyframes.
+pub trait Interpolate: Copy {
+    fn interpolate(self, other: Self, t: f32) -> Self;
+}
+
+impl Interpolate for Vector3<f32> {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+/// Takes the shorter arc: `q` and `-q` are the same rotation, and cgmath's `slerp`
+/// goes the long way around, or divides by zero for opposite quaternions.
+impl Interpolate for Quaternion<f32> {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        let other = if self.dot(other) < 0. { -other } else { other };
+        self.slerp(other, t).normalize()
+    }
+}
+
+impl Interpolate for [f32; 4] {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        let mut result = self;
+        for (value, target) in result.iter_mut().zip(other.iter()) {
+            *value += (target - *value) * t;
+        }
+        result
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe<T> {
+    /// In seconds, from the start of the track.
+    pub time: f32,
+    pub value: T,
+    /// Used between this keyframe and the next one.
+    pub easing: Easing,
+}
+
+impl<T> Keyframe<T> {
+    pub fn new(time: f32, value: T, easing: Easing) -> Self {
+        Keyframe {
+            time,
+            value,
+            easing,
+        }
+    }
+}
+
+/// Keyframes sorted by time.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Interpolate> Track<T> {
+    /// Panics if a time isn't finite, see `try_new` for keyframes from files.
+    pub fn new(keyframes: Vec<Keyframe<T>>) -> Self {
+        Self::try_new(keyframes).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_new(mut keyframes: Vec<Keyframe<T>>) -> Result<Self, AnimationError> {
+        if let Some(keyframe) = keyframes.iter().find(|keyframe| !keyframe.time.is_finite()) {
+            return Err(AnimationError::NonFiniteTime(keyframe.time));
+        }
+        // finite times are totally ordered
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(Track { keyframes })
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |keyframe| keyframe.time)
+    }
+
+    /// Samples the track, holding the first and last values outside of it.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let next = self.keyframes.iter().position(|keyframe| keyframe.time > time);
+        match next {
+            None => self.keyframes.last().map(|keyframe| keyframe.value),
+            Some(0) => Some(self.keyframes[0].value),
+            Some(next) => {
+                let from = &self.keyframes[next - 1];
+                let to = &self.keyframes[next];
+                let t = (time - from.time) / (to.time - from.time);
+                Some(from.value.interpolate(to.value, from.easing.apply(t)))
+            }
+        }
+    }
+}
+
+/// What happens once playback reaches the end of a clip.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopMode {
+    /// Stops on the last frame, clearing `Animation::playing`.
+    Once,
+    /// Starts over.
+    Loop,
+    /// Plays backwards, then forwards again.
+    PingPong,
+}
+
+/// A sampled transform and color.
+#[derive(Clone, Copy, Debug)]
+pub struct Pose {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub color: [f32; 4],
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Pose {
+            translation: Vector3::new(0., 0., 0.),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1., 1., 1.),
+            color: [1., 1., 1., 1.],
+        }
+    }
+}
+
+impl Pose {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    pub fn box_instance(&self) -> BoxInstance {
+        BoxInstance::new(self.matrix(), self.color)
+    }
+}
+
+/// A set of tracks animating a single pose. Missing tracks leave the base pose as is.
+#[derive(Clone, Debug, Default)]
+pub struct Clip {
+    pub translation: Option<Track<Vector3<f32>>>,
+    pub rotation: Option<Track<Quaternion<f32>>>,
+    pub scale: Option<Track<Vector3<f32>>>,
+    pub color: Option<Track<[f32; 4]>>,
+}
+
+impl Clip {
+    pub fn duration(&self) -> f32 {
+        let durations = [
+            self.translation.as_ref().map(Track::duration),
+            self.rotation.as_ref().map(Track::duration),
+            self.scale.as_ref().map(Track::duration),
+            self.color.as_ref().map(Track::duration),
+        ];
+        durations.iter().flatten().fold(0., |a: f32, &b| a.max(b))
+    }
+
+    pub fn sample(&self, time: f32, base: &Pose) -> Pose {
+        Pose {
+            translation: self
+                .translation
+                .as_ref()
+                .and_then(|track| track.sample(time))
+                .unwrap_or(base.translation),
+            rotation: self
+                .rotation
+                .as_ref()
+                .and_then(|track| track.sample(time))
+                .unwrap_or(base.rotation),
+            scale: self
+                .scale
+                .as_ref()
+                .and_then(|track| track.sample(time))
+                .unwrap_or(base.scale),
+            color: self
+                .color
+                .as_ref()
+                .and_then(|track| track.sample(time))
+                .unwrap_or(base.color),
+        }
+    }
+}
+
+/// Plays a clip, advanced by the frame delta time.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub clip: Clip,
+    pub base: Pose,
+    pub loop_mode: LoopMode,
+    /// Playback rate, 1 being real time.
+    pub speed: f32,
+    pub playing: bool,
+    time: f32,
+}
+
+impl Animation {
+    pub fn new(clip: Clip, loop_mode: LoopMode) -> Self {
+        Animation {
+            clip,
+            base: Pose::default(),
+            loop_mode,
+            speed: 1.,
+            playing: true,
+            time: 0.,
+        }
+    }
+
+    /// Time since the start of playback, in seconds.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        if !self.playing {
+            return;
+        }
+        self.time += dt.as_secs_f32() * self.speed;
+        if self.loop_mode == LoopMode::Once {
+            let duration = self.clip.duration();
+            if self.time >= duration {
+                self.time = duration;
+                self.playing = false;
+            }
+        }
+    }
+
+    /// Maps the playback time to a time within the clip.
+    fn clip_time(&self) -> f32 {
+        let duration = self.clip.duration();
+        if duration <= 0. {
+            return 0.;
+        }
+
+        match self.loop_mode {
+            LoopMode::Once => self.time.min(duration),
+            LoopMode::Loop => self.time.rem_euclid(duration),
+            LoopMode::PingPong => {
+                let t = self.time.rem_euclid(2. * duration);
+                if t > duration {
+                    2. * duration - t
+                } else {
+                    t
+                }
+            }
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.clip.sample(self.clip_time(), &self.base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linear_track() -> Track<Vector3<f32>> {
+        Track::new(vec![
+            Keyframe::new(1., Vector3::new(0., 0., 0.), Easing::Linear),
+            Keyframe::new(3., Vector3::new(4., 0., 0.), Easing::Linear),
+        ])
+    }
+
+    #[test]
+    fn track_sampling() {
+        let track = linear_track();
+        assert_eq!(track.sample(0.).unwrap().x, 0.);
+        assert_eq!(track.sample(2.).unwrap().x, 2.);
+        assert_eq!(track.sample(5.).unwrap().x, 4.);
+    }
+
+    #[test]
+    fn non_finite_time() {
+        let keyframes = vec![
+            Keyframe::new(0., Vector3::new(0., 0., 0.), Easing::Linear),
+            Keyframe::new(std::f32::NAN, Vector3::new(1., 0., 0.), Easing::Linear),
+        ];
+        assert!(matches!(Track::try_new(keyframes), Err(AnimationError::NonFiniteTime(_))));
+    }
+
+    #[test]
+    fn ping_pong() {
+        let clip = Clip {
+            translation: Some(linear_track()),
+            ..Clip::default()
+        };
+        let mut animation = Animation::new(clip, LoopMode::PingPong);
+        animation.advance(Duration::from_secs(4));
+        // 4 seconds in, 1 second into the way back
+        assert_eq!(animation.pose().translation.x, 2.);
+    }
+
+    #[test]
+    fn once_stops_at_the_end() {
+        let clip = Clip {
+            translation: Some(linear_track()),
+            ..Clip::default()
+        };
+        let mut animation = Animation::new(clip, LoopMode::Once);
+        animation.advance(Duration::from_secs(2));
+        assert!(animation.playing);
+        animation.advance(Duration::from_secs(2));
+        assert!(!animation.playing);
+        assert_eq!(animation.time(), 3.);
+        assert_eq!(animation.pose().translation.x, 4.);
+    }
+
+    #[test]
+    fn rotation_takes_the_shorter_arc() {
+        use cgmath::{Deg, Rotation, Rotation3};
+
+        let turn = |degrees: f32| Quaternion::from_angle_z(Deg(degrees));
+        // 270° counterclockwise is 90° clockwise
+        let halfway = turn(0.).interpolate(turn(270.), 0.5).rotate_vector(Vector3::unit_x());
+        let expected = Vector3::new(0.5f32.sqrt(), -(0.5f32.sqrt()), 0.);
+        assert!((halfway - expected).magnitude() < 1e-5, "{:?}", halfway);
+
+        // opposite quaternions, the same rotation
+        let full = turn(0.).interpolate(turn(360.), 0.5);
+        assert!(full.is_finite());
+        assert!((full.rotate_vector(Vector3::unit_x()) - Vector3::unit_x()).magnitude() < 1e-5);
+    }
+}
@@ -0,0 +1,14 @@
+#version 450
+
+layout(location = 0) in INTERFACE {
+	vec2 uv;
+} In;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+
+layout(location = 0) out vec4 o_Target;
+
+void main() {
+    o_Target = texture(sampler2D(t_source, s_source), In.uv);
+}
@@ -1,56 +1,103 @@
+use crate::camera::Camera;
+use crate::lighting::{Light, LightUniforms};
+use crate::mesh::Mesh;
+use crate::msaa::{self, SampleCountError};
+use crate::reflect;
+#[cfg(feature = "image")]
+use crate::texture::SamplerOptions;
+use crate::texture::Texture;
 use bytemuck::{Pod, Zeroable};
 use std::borrow::Cow::Borrowed;
+#[cfg(feature = "image")]
+use std::path::Path;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
-#[derive(Clone, Copy)]
-struct Vertex {
-    _pos: [f32; 4],
-    _tex_coord: [f32; 2],
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    pub pos: [f32; 4],
+    pub tex_coord: [f32; 2],
+    pub normal: [f32; 3],
+    /// Multiplied with the instance color.
+    pub color: [f32; 4],
 }
 
 unsafe impl Pod for Vertex {}
 unsafe impl Zeroable for Vertex {}
 
-fn vertex(pos: [i8; 3], tc: [i8; 2]) -> Vertex {
-    Vertex {
-        _pos: [pos[0] as f32, pos[1] as f32, pos[2] as f32, 1.0],
-        _tex_coord: [tc[0] as f32, tc[1] as f32],
+impl Vertex {
+    /// A white vertex.
+    pub fn new(pos: [f32; 3], tex_coord: [f32; 2], normal: [f32; 3]) -> Self {
+        Vertex {
+            pos: [pos[0], pos[1], pos[2], 1.0],
+            tex_coord,
+            normal,
+            color: [1.0; 4],
+        }
     }
 }
 
+/// Per-instance data: each box is the unit cube from `create_vertices`,
+/// or the mesh given to `BoxRenderer::set_mesh`, moved in place by `transform`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BoxInstance {
+    pub transform: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+
+unsafe impl Pod for BoxInstance {}
+unsafe impl Zeroable for BoxInstance {}
+
+impl BoxInstance {
+    pub fn new(transform: cgmath::Matrix4<f32>, color: [f32; 4]) -> Self {
+        BoxInstance {
+            transform: transform.into(),
+            color,
+        }
+    }
+}
+
+fn vertex(pos: [i8; 3], tc: [i8; 2], normal: [i8; 3]) -> Vertex {
+    Vertex::new(
+        [pos[0] as f32, pos[1] as f32, pos[2] as f32],
+        [tc[0] as f32, tc[1] as f32],
+        [normal[0] as f32, normal[1] as f32, normal[2] as f32],
+    )
+}
+
 fn create_vertices() -> (Vec<Vertex>, Vec<u16>) {
     let vertex_data = [
         // top (0, 0, 1)
-        vertex([-1, -1, 1], [0, 0]),
-        vertex([1, -1, 1], [1, 0]),
-        vertex([1, 1, 1], [1, 1]),
-        vertex([-1, 1, 1], [0, 1]),
+        vertex([-1, -1, 1], [0, 0], [0, 0, 1]),
+        vertex([1, -1, 1], [1, 0], [0, 0, 1]),
+        vertex([1, 1, 1], [1, 1], [0, 0, 1]),
+        vertex([-1, 1, 1], [0, 1], [0, 0, 1]),
         // bottom (0, 0, -1)
-        vertex([-1, 1, -1], [1, 0]),
-        vertex([1, 1, -1], [0, 0]),
-        vertex([1, -1, -1], [0, 1]),
-        vertex([-1, -1, -1], [1, 1]),
+        vertex([-1, 1, -1], [1, 0], [0, 0, -1]),
+        vertex([1, 1, -1], [0, 0], [0, 0, -1]),
+        vertex([1, -1, -1], [0, 1], [0, 0, -1]),
+        vertex([-1, -1, -1], [1, 1], [0, 0, -1]),
         // right (1, 0, 0)
-        vertex([1, -1, -1], [0, 0]),
-        vertex([1, 1, -1], [1, 0]),
-        vertex([1, 1, 1], [1, 1]),
-        vertex([1, -1, 1], [0, 1]),
+        vertex([1, -1, -1], [0, 0], [1, 0, 0]),
+        vertex([1, 1, -1], [1, 0], [1, 0, 0]),
+        vertex([1, 1, 1], [1, 1], [1, 0, 0]),
+        vertex([1, -1, 1], [0, 1], [1, 0, 0]),
         // left (-1, 0, 0)
-        vertex([-1, -1, 1], [1, 0]),
-        vertex([-1, 1, 1], [0, 0]),
-        vertex([-1, 1, -1], [0, 1]),
-        vertex([-1, -1, -1], [1, 1]),
+        vertex([-1, -1, 1], [1, 0], [-1, 0, 0]),
+        vertex([-1, 1, 1], [0, 0], [-1, 0, 0]),
+        vertex([-1, 1, -1], [0, 1], [-1, 0, 0]),
+        vertex([-1, -1, -1], [1, 1], [-1, 0, 0]),
         // front (0, 1, 0)
-        vertex([1, 1, -1], [1, 0]),
-        vertex([-1, 1, -1], [0, 0]),
-        vertex([-1, 1, 1], [0, 1]),
-        vertex([1, 1, 1], [1, 1]),
+        vertex([1, 1, -1], [1, 0], [0, 1, 0]),
+        vertex([-1, 1, -1], [0, 0], [0, 1, 0]),
+        vertex([-1, 1, 1], [0, 1], [0, 1, 0]),
+        vertex([1, 1, 1], [1, 1], [0, 1, 0]),
         // back (0, -1, 0)
-        vertex([1, -1, 1], [0, 0]),
-        vertex([-1, -1, 1], [1, 0]),
-        vertex([-1, -1, -1], [1, 1]),
-        vertex([1, -1, -1], [0, 1]),
+        vertex([1, -1, 1], [0, 0], [0, -1, 0]),
+        vertex([-1, -1, 1], [1, 0], [0, -1, 0]),
+        vertex([-1, -1, -1], [1, 1], [0, -1, 0]),
+        vertex([1, -1, -1], [0, 1], [0, -1, 0]),
     ];
 
     let index_data: &[u16] = &[
@@ -65,71 +112,174 @@ fn create_vertices() -> (Vec<Vertex>, Vec<u16>) {
     (vertex_data.to_vec(), index_data.to_vec())
 }
 
-pub struct BoxRenderer {
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// A mesh, its texture and where to draw it.
+struct MeshDraw {
     vertex_buf: wgpu::Buffer,
     index_buf: wgpu::Buffer,
     index_count: usize,
-    bind_group: wgpu::BindGroup,
-    uniform_buf: wgpu::Buffer,
-    pipeline: wgpu::RenderPipeline,
+    index_format: wgpu::IndexFormat,
+    instance_buf: wgpu::Buffer,
+    instance_capacity: usize,
+    instance_count: usize,
+    texture_bind_group: wgpu::BindGroup,
+    /// None when using the solid color fallback.
+    texture: Option<Texture>,
 }
 
-impl BoxRenderer {
-    fn generate_matrix(width: f32, height: f32) -> cgmath::Matrix4<f32> {
-        let zoom = 100.;
-
-        let fac = 1. / (2. * zoom);
-        let h_width = width * fac;
-        let h_height = height * fac;
-
-        let mx_projection: cgmath::Matrix4<f32> = cgmath::Ortho{
-            left: -h_width,
-            right: h_width,
-            bottom: -h_height,
-            top: h_height,
-            near: 1.,
-            far: 10.,
-        }.into();
-
-        // let mx_projection = cgmath::perspective(cgmath::Deg(45f32), aspect_ratio, 1.0, 10.0);
-        let mx_view = cgmath::Matrix4::look_at(
-            cgmath::Point3::new(1.5f32, -5.0, 3.0),
-            cgmath::Point3::new(0f32, 0.0, 0.0),
-            cgmath::Vector3::unit_z(),
-        );
-        let mx_correction = crate::framework::OPENGL_TO_WGPU_MATRIX;
-        mx_correction * mx_projection * mx_view
+impl MeshDraw {
+    fn new(mesh: &Mesh, texture_bind_group: wgpu::BindGroup, device: &wgpu::Device) -> Self {
+        let (vertex_buf, index_buf, index_format) = Self::upload(mesh, device);
+        MeshDraw {
+            vertex_buf,
+            index_buf,
+            index_count: mesh.indices.len(),
+            index_format,
+            instance_buf: BoxRenderer::create_instance_buffer(device, 1),
+            instance_capacity: 1,
+            instance_count: 0,
+            texture_bind_group,
+            texture: None,
+        }
     }
 
-    pub fn new(
-        format: wgpu::TextureFormat,
-        width: u32,
-        height: u32,
-        device: &wgpu::Device,
-        _queue: &wgpu::Queue,
-    ) -> Self {
-        use std::mem;
-
-        // Create the vertex and index buffers
-        let vertex_size = mem::size_of::<Vertex>();
-        let (vertex_data, index_data) = create_vertices();
-
+    fn upload(mesh: &Mesh, device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer, wgpu::IndexFormat) {
         let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&vertex_data),
+            contents: bytemuck::cast_slice(&mesh.vertices),
             usage: wgpu::BufferUsage::VERTEX,
         });
 
+        let index_format = mesh.index_format();
+        let index_data = match index_format {
+            wgpu::IndexFormat::Uint16 => {
+                let indices: Vec<u16> = mesh.indices.iter().map(|&index| index as u16).collect();
+                bytemuck::cast_slice(&indices).to_vec()
+            }
+            wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(&mesh.indices).to_vec(),
+        };
         let index_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&index_data),
+            contents: &index_data,
             usage: wgpu::BufferUsage::INDEX,
         });
+        (vertex_buf, index_buf, index_format)
+    }
 
-        // Create pipeline layout
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    fn set_mesh(&mut self, mesh: &Mesh, device: &wgpu::Device) {
+        let (vertex_buf, index_buf, index_format) = Self::upload(mesh, device);
+        self.vertex_buf = vertex_buf;
+        self.index_buf = index_buf;
+        self.index_count = mesh.indices.len();
+        self.index_format = index_format;
+    }
+
+    /// The instance buffer is only reallocated when it grows.
+    fn set_instances(&mut self, instances: &[BoxInstance], device: &wgpu::Device, queue: &wgpu::Queue) {
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instance_buf = BoxRenderer::create_instance_buffer(device, self.instance_capacity);
+        }
+        if !instances.is_empty() {
+            queue.write_buffer(&self.instance_buf, 0, bytemuck::cast_slice(instances));
+        }
+        self.instance_count = instances.len();
+    }
+}
+
+/// Draws instances of meshes: the unit cube unless `set_mesh` is called,
+/// and those added with `add_mesh`.
+/// The index format is part of the pipeline, so there is one per format.
+struct Pipelines {
+    uint16: wgpu::RenderPipeline,
+    uint32: wgpu::RenderPipeline,
+}
+
+impl Pipelines {
+    fn get(&self, index_format: wgpu::IndexFormat) -> &wgpu::RenderPipeline {
+        match index_format {
+            wgpu::IndexFormat::Uint16 => &self.uint16,
+            wgpu::IndexFormat::Uint32 => &self.uint32,
+        }
+    }
+}
+
+pub struct BoxRenderer {
+    /// The first one is the main mesh, which the single mesh methods work on.
+    draws: Vec<MeshDraw>,
+    bind_group: wgpu::BindGroup,
+    uniform_buf: wgpu::Buffer,
+    light_buf: wgpu::Buffer,
+    light: Light,
+    light_changed: bool,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipelines: Pipelines,
+    /// SPIR-V of the current pipelines, to rebuild them with other settings.
+    vs_words: Vec<u32>,
+    fs_words: Vec<u32>,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+    /// The multisampled color target, resolved into the view given to `render`.
+    /// None without MSAA.
+    msaa_view: Option<wgpu::TextureView>,
+    depth_view: wgpu::TextureView,
+    camera: Camera,
+}
+
+impl BoxRenderer {
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity * std::mem::size_of::<BoxInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_attachment_view(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            format,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
             label: None,
-            entries: Borrowed(&[wgpu::BindGroupLayoutEntry::new(
+        });
+        texture.create_default_view()
+    }
+
+    fn create_msaa_view(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Option<wgpu::TextureView> {
+        if sample_count > 1 {
+            Some(Self::create_attachment_view(device, format, width, height, sample_count))
+        } else {
+            None
+        }
+    }
+
+    fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+        [
+            wgpu::BindGroupLayoutEntry::new(
                 0,
                 wgpu::ShaderStage::VERTEX,
                 wgpu::BindingType::UniformBuffer {
@@ -137,38 +287,51 @@ impl BoxRenderer {
                     // TODO: 64 should be sizeof(mat4)
                     min_binding_size: wgpu::BufferSize::new(64),
                 },
-            )]),
-        });
+            ),
+            wgpu::BindGroupLayoutEntry::new(
+                1,
+                wgpu::ShaderStage::FRAGMENT,
+                wgpu::BindingType::UniformBuffer {
+                    dynamic: false,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<LightUniforms>() as wgpu::BufferAddress,
+                    ),
+                },
+            ),
+        ]
+    }
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            bind_group_layouts: Borrowed(&[&bind_group_layout]),
-            push_constant_ranges: Borrowed(&[]),
-        });
+    fn texture_layout_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+        Texture::layout_entries(0, wgpu::ShaderStage::FRAGMENT)
+    }
 
-        let mx_total = Self::generate_matrix(width as f32, height as f32);
-        let mx_ref: &[f32; 16] = mx_total.as_ref();
-        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(mx_ref),
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-        });
+    fn validate_shaders(vs_words: &[u32], fs_words: &[u32]) -> Result<(), reflect::InterfaceError> {
+        reflect::validate_layouts(
+            &[&Self::layout_entries(), &Self::texture_layout_entries()],
+            &[
+                (wgpu::ShaderStage::VERTEX, vs_words),
+                (wgpu::ShaderStage::FRAGMENT, fs_words),
+            ],
+        )
+    }
 
-        // Create bind group
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: Borrowed(&[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer(uniform_buf.slice(..)),
-            }]),
-            label: None,
-        });
+    /// The shaders must have gone through `validate_shaders`.
+    fn create_pipeline(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        index_format: wgpu::IndexFormat,
+        vs_words: &[u32],
+        fs_words: &[u32],
+    ) -> wgpu::RenderPipeline {
+        use std::mem;
 
-        // Create the render pipeline
-        let vs_module = device.create_shader_module(wgpu::include_spirv!("shader.vert.spv"));
-        let fs_module = device.create_shader_module(wgpu::include_spirv!("shader.frag.spv"));
+        let vs_module = device.create_shader_module(wgpu::ShaderModuleSource::SpirV(Borrowed(vs_words)));
+        let fs_module = device.create_shader_module(wgpu::ShaderModuleSource::SpirV(Borrowed(fs_words)));
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            layout: &pipeline_layout,
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout: pipeline_layout,
             vertex_stage: wgpu::ProgrammableStageDescriptor {
                 module: &vs_module,
                 entry_point: Borrowed("main"),
@@ -189,59 +352,392 @@ impl BoxRenderer {
                 alpha_blend: wgpu::BlendDescriptor::REPLACE,
                 write_mask: wgpu::ColorWrite::ALL,
             }]),
-            depth_stencil_state: None,
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilStateDescriptor::default(),
+            }),
             vertex_state: wgpu::VertexStateDescriptor {
-                index_format: wgpu::IndexFormat::Uint16,
-                vertex_buffers: Borrowed(&[wgpu::VertexBufferDescriptor {
-                    stride: vertex_size as wgpu::BufferAddress,
-                    step_mode: wgpu::InputStepMode::Vertex,
-                    attributes: Borrowed(&[
-                        wgpu::VertexAttributeDescriptor {
-                            format: wgpu::VertexFormat::Float4,
-                            offset: 0,
-                            shader_location: 0,
-                        },
-                        wgpu::VertexAttributeDescriptor {
-                            format: wgpu::VertexFormat::Float2,
-                            offset: 4 * 4,
-                            shader_location: 1,
-                        },
-                    ]),
-                }]),
+                index_format,
+                vertex_buffers: Borrowed(&[
+                    wgpu::VertexBufferDescriptor {
+                        stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::InputStepMode::Vertex,
+                        attributes: Borrowed(&[
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float4,
+                                offset: 0,
+                                shader_location: 0,
+                            },
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float2,
+                                offset: 4 * 4,
+                                shader_location: 1,
+                            },
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float3,
+                                offset: 4 * 4 + 4 * 2,
+                                shader_location: 7,
+                            },
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float4,
+                                offset: 4 * 4 + 4 * 2 + 4 * 3,
+                                shader_location: 8,
+                            },
+                        ]),
+                    },
+                    wgpu::VertexBufferDescriptor {
+                        stride: mem::size_of::<BoxInstance>() as wgpu::BufferAddress,
+                        step_mode: wgpu::InputStepMode::Instance,
+                        attributes: Borrowed(&[
+                            // transform: mat4, one location per column
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float4,
+                                offset: 0,
+                                shader_location: 2,
+                            },
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float4,
+                                offset: 4 * 4,
+                                shader_location: 3,
+                            },
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float4,
+                                offset: 4 * 4 * 2,
+                                shader_location: 4,
+                            },
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float4,
+                                offset: 4 * 4 * 3,
+                                shader_location: 5,
+                            },
+                            // color: vec4
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float4,
+                                offset: 4 * 4 * 4,
+                                shader_location: 6,
+                            },
+                        ]),
+                    },
+                ]),
             },
-            sample_count: 1,
+            sample_count,
             sample_mask: !0,
             alpha_to_coverage_enabled: false,
+        })
+    }
+
+    pub fn new(
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self, reflect::InterfaceError> {
+        let vs_words = reflect::spirv_words(include_bytes!("shader.vert.spv"))?;
+        let fs_words = reflect::spirv_words(include_bytes!("shader.frag.spv"))?;
+        Self::validate_shaders(&vs_words, &fs_words)?;
+
+        // Create the vertex and index buffers
+        let (vertex_data, index_data) = create_vertices();
+        let cube = Mesh::new(vertex_data, index_data.into_iter().map(u32::from).collect());
+
+        // Create pipeline layout
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: Borrowed(&Self::layout_entries()),
+        });
+
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: Borrowed(&Self::texture_layout_entries()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: Borrowed(&[&bind_group_layout, &texture_bind_group_layout]),
+            push_constant_ranges: Borrowed(&[]),
+        });
+
+        let mut camera = Camera::new(width, height);
+        camera.take_changed();
+        let mx_total = camera.matrix();
+        let mx_ref: &[f32; 16] = mx_total.as_ref();
+        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Uniform Buffer"),
+            contents: bytemuck::cast_slice(mx_ref),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let light = Light::default();
+        let light_uniforms = [LightUniforms::new(&light, camera.eye())];
+        let light_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: bytemuck::cast_slice(&light_uniforms),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         });
 
+        // Create bind group
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: Borrowed(&[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(uniform_buf.slice(..)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(light_buf.slice(..)),
+                },
+            ]),
+            label: None,
+        });
+
+        // Until a texture is set, sampling a white pixel leaves the instance color as is
+        let texture_bind_group = Texture::white(device, queue).bind_group(device, &texture_bind_group_layout);
+        let mut draw = MeshDraw::new(&cube, texture_bind_group, device);
+        let default_instance = [BoxInstance::new(cgmath::SquareMatrix::identity(), [1.0, 0.0, 0.0, 1.0])];
+        draw.set_instances(&default_instance, device, queue);
+
+        // Create the render pipelines
+        let pipelines = Self::create_pipelines(device, &pipeline_layout, format, 1, &vs_words, &fs_words);
+
         // Done
-        BoxRenderer {
-            vertex_buf,
-            index_buf,
-            index_count: index_data.len(),
+        Ok(BoxRenderer {
+            pipelines,
+            draws: vec![draw],
             bind_group,
             uniform_buf,
-            pipeline,
+            light_buf,
+            light,
+            light_changed: false,
+            texture_bind_group_layout,
+            pipeline_layout,
+            vs_words,
+            fs_words,
+            format,
+            width,
+            height,
+            sample_count: 1,
+            msaa_view: None,
+            depth_view: Self::create_attachment_view(device, DEPTH_FORMAT, width, height, 1),
+            camera,
+        })
+    }
+
+    /// Builds a pipeline for each index format, so that any mesh can be drawn.
+    fn create_pipelines(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        vs_words: &[u32],
+        fs_words: &[u32],
+    ) -> Pipelines {
+        let create = |index_format| {
+            Self::create_pipeline(
+                device,
+                pipeline_layout,
+                format,
+                sample_count,
+                index_format,
+                vs_words,
+                fs_words,
+            )
+        };
+        Pipelines {
+            uint16: create(wgpu::IndexFormat::Uint16),
+            uint32: create(wgpu::IndexFormat::Uint32),
         }
     }
 
-    pub fn resize(&mut self, width: u32, height: u32, _device: &wgpu::Device, queue: &wgpu::Queue) {
-        let mx_total = Self::generate_matrix(width as f32, height as f32);
-        let mx_ref: &[f32; 16] = mx_total.as_ref();
-        queue.write_buffer(&self.uniform_buf, 0, bytemuck::cast_slice(mx_ref));
+    /// Rebuilds the pipelines from new SPIR-V. On error, the current pipelines are kept.
+    #[cfg(feature = "hot-reload")]
+    pub fn set_shaders(
+        &mut self,
+        device: &wgpu::Device,
+        vs_words: &[u32],
+        fs_words: &[u32],
+    ) -> Result<(), reflect::InterfaceError> {
+        Self::validate_shaders(vs_words, fs_words)?;
+        self.pipelines = Self::create_pipelines(
+            device,
+            &self.pipeline_layout,
+            self.format,
+            self.sample_count,
+            vs_words,
+            fs_words,
+        );
+        self.vs_words = vs_words.to_vec();
+        self.fs_words = fs_words.to_vec();
+        Ok(())
+    }
+
+    /// Draws instances of `mesh` instead of the main one.
+    pub fn set_mesh(&mut self, mesh: &Mesh, device: &wgpu::Device) {
+        self.replace_mesh(0, mesh, device);
+    }
+
+    /// Same as `set_mesh`, for a mesh from `add_mesh`. Its texture and instances are kept.
+    pub fn replace_mesh(&mut self, index: usize, mesh: &Mesh, device: &wgpu::Device) {
+        self.draws[index].set_mesh(mesh, device);
+    }
+
+    /// Adds a mesh to draw along the main one, with its own texture and instances.
+    /// Returns the index to give to `set_mesh_instances`.
+    pub fn add_mesh(
+        &mut self,
+        mesh: &Mesh,
+        texture: Option<Texture>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> usize {
+        let texture_bind_group = match &texture {
+            Some(texture) => texture.bind_group(device, &self.texture_bind_group_layout),
+            None => Texture::white(device, queue).bind_group(device, &self.texture_bind_group_layout),
+        };
+        let mut draw = MeshDraw::new(mesh, texture_bind_group, device);
+        draw.texture = texture;
+        self.draws.push(draw);
+        self.draws.len() - 1
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Switches to `sample_count`x MSAA, 1 disabling it. On error, the current count is kept.
+    pub fn set_sample_count(
+        &mut self,
+        sample_count: u32,
+        adapter_info: &wgpu::AdapterInfo,
+        device: &wgpu::Device,
+    ) -> Result<(), SampleCountError> {
+        msaa::validate_sample_count(sample_count, adapter_info, self.format)?;
+        if sample_count == self.sample_count {
+            return Ok(());
+        }
+
+        self.pipelines = Self::create_pipelines(
+            device,
+            &self.pipeline_layout,
+            self.format,
+            sample_count,
+            &self.vs_words,
+            &self.fs_words,
+        );
+        self.sample_count = sample_count;
+        self.create_targets(device);
+        Ok(())
+    }
+
+    fn create_targets(&mut self, device: &wgpu::Device) {
+        self.msaa_view = Self::create_msaa_view(device, self.format, self.width, self.height, self.sample_count);
+        self.depth_view =
+            Self::create_attachment_view(device, DEPTH_FORMAT, self.width, self.height, self.sample_count);
+    }
+
+    /// Maps a texture onto each face of the boxes, multiplied by the instance color.
+    pub fn set_texture(&mut self, texture: Texture, device: &wgpu::Device) {
+        self.draws[0].texture_bind_group = texture.bind_group(device, &self.texture_bind_group_layout);
+        self.draws[0].texture = Some(texture);
+    }
+
+    /// Loads a PNG or JPEG image, and uses it as the box texture.
+    #[cfg(feature = "image")]
+    pub fn load_texture(
+        &mut self,
+        path: &Path,
+        options: &SamplerOptions,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> image::ImageResult<()> {
+        let texture = Texture::load(device, queue, path, options)?;
+        self.set_texture(texture, device);
+        Ok(())
+    }
+
+    /// Goes back to drawing solid colored boxes.
+    pub fn clear_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.draws[0].texture_bind_group =
+            Texture::white(device, queue).bind_group(device, &self.texture_bind_group_layout);
+        self.draws[0].texture = None;
     }
 
+    pub fn texture(&self) -> Option<&Texture> {
+        self.draws[0].texture.as_ref()
+    }
+
+    pub fn light(&self) -> &Light {
+        &self.light
+    }
+
+    pub fn set_light(&mut self, light: Light) {
+        self.light = light;
+        self.light_changed = true;
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    /// A zero size, as given for minimized windows, is ignored.
+    pub fn resize(&mut self, width: u32, height: u32, device: &wgpu::Device, _queue: &wgpu::Queue) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.camera.resize(width, height);
+        self.width = width;
+        self.height = height;
+        self.create_targets(device);
+    }
+
+    /// Replaces the boxes to draw. The instance buffer is only reallocated when it grows.
+    pub fn set_instances(&mut self, instances: &[BoxInstance], device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.draws[0].set_instances(instances, device, queue);
+    }
+
+    /// Same as `set_instances`, for a mesh from `add_mesh`.
+    pub fn set_mesh_instances(
+        &mut self,
+        mesh: usize,
+        instances: &[BoxInstance],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        self.draws[mesh].set_instances(instances, device, queue);
+    }
+
+    /// Draws the boxes into `view`. With MSAA, they are drawn into a multisampled
+    /// target first, which gets resolved into `view`.
     pub fn render(
         &mut self,
         view: &wgpu::TextureView,
         _device: &wgpu::Device,
-        _queue: &wgpu::Queue,
+        queue: &wgpu::Queue,
         encoder: &mut wgpu::CommandEncoder,
     ) {
+        let camera_changed = self.camera.take_changed();
+        if camera_changed {
+            let mx_total = self.camera.matrix();
+            let mx_ref: &[f32; 16] = mx_total.as_ref();
+            queue.write_buffer(&self.uniform_buf, 0, bytemuck::cast_slice(mx_ref));
+        }
+        // specular highlights depend on the eye position
+        if camera_changed || self.light_changed {
+            let light_uniforms = [LightUniforms::new(&self.light, self.camera.eye())];
+            queue.write_buffer(&self.light_buf, 0, bytemuck::cast_slice(&light_uniforms));
+            self.light_changed = false;
+        }
+
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             color_attachments: Borrowed(&[wgpu::RenderPassColorAttachmentDescriptor {
-                attachment: view,
-                resolve_target: None,
+                attachment: self.msaa_view.as_ref().unwrap_or(view),
+                resolve_target: self.msaa_view.as_ref().map(|_| view),
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color {
                         r: 0.,
@@ -249,18 +745,30 @@ impl BoxRenderer {
                         b: 0.,
                         a: 1.0,
                     }),
-                    store: true,
+                    // only the resolved image is used after the pass
+                    store: self.msaa_view.is_none(),
                 },
             }]),
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                attachment: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
         });
-        rpass.push_debug_group("Prepare data for draw.");
-        rpass.set_pipeline(&self.pipeline);
         rpass.set_bind_group(0, &self.bind_group, &[]);
-        rpass.set_index_buffer(self.index_buf.slice(..));
-        rpass.set_vertex_buffer(0, self.vertex_buf.slice(..));
-        rpass.pop_debug_group();
-        rpass.insert_debug_marker("Draw!");
-        rpass.draw_indexed(0..self.index_count as u32, 0, 0..1);
+        for draw in self.draws.iter().filter(|draw| draw.instance_count > 0 && draw.index_count > 0) {
+            rpass.push_debug_group("Prepare data for draw.");
+            rpass.set_pipeline(self.pipelines.get(draw.index_format));
+            rpass.set_bind_group(1, &draw.texture_bind_group, &[]);
+            rpass.set_index_buffer(draw.index_buf.slice(..));
+            rpass.set_vertex_buffer(0, draw.vertex_buf.slice(..));
+            rpass.set_vertex_buffer(1, draw.instance_buf.slice(..));
+            rpass.pop_debug_group();
+            rpass.insert_debug_marker("Draw!");
+            rpass.draw_indexed(0..draw.index_count as u32, 0, 0..draw.instance_count as u32);
+        }
     }
 }
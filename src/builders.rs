@@ -1,6 +1,7 @@
+use crate::error::AnimaError;
 use std::borrow::Cow::Borrowed;
 
-pub async fn request_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface) -> wgpu::Adapter {
+pub async fn request_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface) -> Result<wgpu::Adapter, AnimaError> {
     instance
         .request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::Default,
@@ -8,11 +9,11 @@ pub async fn request_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface)
             compatible_surface: Some(&surface),
         })
         .await
-        .expect("Failed to find an appropiate adapter")
+        .ok_or(AnimaError::NoAdapter)
 }
 
-pub async fn request_default_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
-    adapter
+pub async fn request_default_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), AnimaError> {
+    let device = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
                 features: wgpu::Features::empty(),
@@ -21,8 +22,8 @@ pub async fn request_default_device(adapter: &wgpu::Adapter) -> (wgpu::Device, w
             },
             None,
         )
-        .await
-        .expect("Failed to create device")
+        .await?;
+    Ok(device)
 }
 
 pub struct SwapChainDescBuilder {
@@ -44,6 +45,16 @@ impl SwapChainDescBuilder {
         }
     }
 
+    pub fn format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
     pub fn build(self) -> wgpu::SwapChainDescriptor {
         wgpu::SwapChainDescriptor {
             usage: self.usage,
@@ -127,6 +138,7 @@ pub struct RenderPipelineDescBuilder<'a> {
     vertex_state: wgpu::VertexStateDescriptor<'a>,
     alpha_to_coverage_enabled: bool,
     rasterization_state: Option<wgpu::RasterizationStateDescriptor>,
+    depth_stencil_state: Option<wgpu::DepthStencilStateDescriptor>,
     sample_count: u32,
     sample_mask: u32,
 }
@@ -148,6 +160,7 @@ impl<'a> RenderPipelineDescBuilder<'a> {
             },
             alpha_to_coverage_enabled: false,
             rasterization_state: None,
+            depth_stencil_state: None,
             sample_count: 1,
             sample_mask: !0,
         }
@@ -163,6 +176,11 @@ impl<'a> RenderPipelineDescBuilder<'a> {
         self
     }
 
+    pub fn depth_stencil_state(mut self, depth_stencil_state: wgpu::DepthStencilStateDescriptor) -> Self {
+        self.depth_stencil_state = Some(depth_stencil_state);
+        self
+    }
+
     pub fn sample_count(mut self, sample_count: u32) -> Self {
         self.sample_count = sample_count;
         self
@@ -186,7 +204,7 @@ impl<'a> RenderPipelineDescBuilder<'a> {
             rasterization_state: self.rasterization_state,
             primitive_topology: self.primitive_topology,
             color_states: self.color_states,
-            depth_stencil_state: None,
+            depth_stencil_state: self.depth_stencil_state,
             vertex_state: self.vertex_state,
             sample_count: self.sample_count,
             sample_mask: self.sample_mask,
@@ -0,0 +1,302 @@
+use cgmath::{InnerSpace, Matrix4, Point3, Rad, Vector3};
+#[cfg(feature = "winit-runner")]
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Orthographic,
+    Perspective,
+}
+
+/// Radians per pixel of mouse motion while orbiting.
+#[cfg(feature = "winit-runner")]
+const ORBIT_SPEED: f32 = 0.01;
+/// Radians per arrow key press.
+#[cfg(feature = "winit-runner")]
+const KEY_ORBIT_STEP: f32 = 0.1;
+/// Zoom factor per scroll wheel line.
+const SCROLL_FACTOR: f32 = 1.1;
+/// Scroll pixels worth one scroll wheel line, for touchpads.
+#[cfg(feature = "winit-runner")]
+const PIXELS_PER_LINE: f32 = 20.;
+/// Keeps the camera from flipping over the poles.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+/// Keeps the target in front of the near plane, in perspective mode.
+const MIN_DISTANCE: f32 = 0.2;
+const MAX_DISTANCE: f32 = 1000.;
+/// Pixels per world unit bounds, in orthographic mode.
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 10000.;
+
+/// An orbit camera, looking at `target` from `distance` away.
+///
+/// With `handle_event`, left drag orbits, right or middle drag pans, the scroll wheel
+/// zooms and P switches between the orthographic and perspective projections.
+/// Other input sources can call `orbit`, `pan` and `scroll` directly.
+pub struct Camera {
+    target: Point3<f32>,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+    /// Pixels per world unit, in orthographic mode.
+    zoom: f32,
+    /// Vertical field of view, in perspective mode.
+    fovy: Rad<f32>,
+    projection: Projection,
+    width: f32,
+    height: f32,
+
+    #[cfg(feature = "winit-runner")]
+    orbiting: bool,
+    #[cfg(feature = "winit-runner")]
+    panning: bool,
+    #[cfg(feature = "winit-runner")]
+    last_cursor: Option<(f64, f64)>,
+
+    changed: bool,
+}
+
+impl Camera {
+    pub fn new(width: u32, height: u32) -> Self {
+        // the eye used to be hardcoded at (1.5, -5, 3)
+        let eye = Vector3::new(1.5f32, -5.0, 3.0);
+        let distance = eye.magnitude();
+
+        Camera {
+            target: Point3::new(0., 0., 0.),
+            yaw: eye.y.atan2(eye.x),
+            pitch: (eye.z / distance).asin(),
+            distance,
+            zoom: 100.,
+            fovy: cgmath::Deg(45f32).into(),
+            projection: Projection::Orthographic,
+            width: width as f32,
+            height: height as f32,
+            #[cfg(feature = "winit-runner")]
+            orbiting: false,
+            #[cfg(feature = "winit-runner")]
+            panning: false,
+            #[cfg(feature = "winit-runner")]
+            last_cursor: None,
+            changed: true,
+        }
+    }
+
+    /// Zero sizes, as when the window is minimized, are ignored: they have no aspect ratio.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.width = width as f32;
+        self.height = height as f32;
+        self.changed = true;
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.changed = true;
+    }
+
+    /// Moves the camera to `eye`, orbiting around `target` from now on.
+    pub fn look_at(&mut self, eye: Point3<f32>, target: Point3<f32>) {
+        let offset = eye - target;
+        self.target = target;
+        self.distance = offset.magnitude().max(MIN_DISTANCE).min(MAX_DISTANCE);
+        self.yaw = offset.y.atan2(offset.x);
+        self.pitch = (offset.z / self.distance).asin().max(-MAX_PITCH).min(MAX_PITCH);
+        self.changed = true;
+    }
+
+    pub fn set_fovy(&mut self, fovy: Rad<f32>) {
+        self.fovy = fovy;
+        self.changed = true;
+    }
+
+    /// Zooms so that `height` world units fill the viewport, in orthographic mode.
+    pub fn fit_height(&mut self, height: f32) {
+        self.zoom = (self.height / height).max(MIN_ZOOM).min(MAX_ZOOM);
+        self.changed = true;
+    }
+
+    /// Returns whether the matrix changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        let direction = Vector3::new(
+            self.pitch.cos() * self.yaw.cos(),
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+        );
+        self.target + direction * self.distance
+    }
+
+    /// Turns around the target, in radians.
+    pub fn orbit(&mut self, d_yaw: f32, d_pitch: f32) {
+        self.yaw += d_yaw;
+        self.pitch = (self.pitch + d_pitch).max(-MAX_PITCH).min(MAX_PITCH);
+        self.changed = true;
+    }
+
+    /// Moves the target along the view plane, by a cursor motion in pixels.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        // world units per pixel, at the target's depth
+        let scale = match self.projection {
+            Projection::Orthographic => 1. / self.zoom,
+            Projection::Perspective => {
+                2. * self.distance * (self.fovy().0 / 2.).tan() / self.height
+            }
+        };
+        let forward = (self.target - self.eye()).normalize();
+        let right = forward.cross(Vector3::unit_z()).normalize();
+        let up = right.cross(forward);
+        self.target += (up * dy - right * dx) * scale;
+        self.changed = true;
+    }
+
+    /// Zooms in by `lines` scroll wheel lines, out when negative.
+    pub fn scroll(&mut self, lines: f32) {
+        let factor = SCROLL_FACTOR.powf(lines);
+        match self.projection {
+            Projection::Orthographic => {
+                self.zoom = (self.zoom * factor).max(MIN_ZOOM).min(MAX_ZOOM)
+            }
+            Projection::Perspective => {
+                self.distance = (self.distance / factor).max(MIN_DISTANCE).min(MAX_DISTANCE)
+            }
+        }
+        self.changed = true;
+    }
+
+    fn fovy(&self) -> Rad<f32> {
+        self.fovy
+    }
+
+    /// Updates the camera from a window event. Returns whether the event was used.
+    #[cfg(feature = "winit-runner")]
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match *event {
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.orbiting = pressed,
+                    MouseButton::Right | MouseButton::Middle => self.panning = pressed,
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let cursor = (position.x, position.y);
+                if let Some((last_x, last_y)) = self.last_cursor {
+                    let dx = (cursor.0 - last_x) as f32;
+                    let dy = (cursor.1 - last_y) as f32;
+                    if self.orbiting {
+                        self.orbit(-dx * ORBIT_SPEED, dy * ORBIT_SPEED);
+                    } else if self.panning {
+                        self.pan(dx, dy);
+                    }
+                }
+                self.last_cursor = Some(cursor);
+                self.orbiting || self.panning
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.last_cursor = None;
+                false
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                self.scroll(lines);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                match key {
+                    VirtualKeyCode::Left => self.orbit(KEY_ORBIT_STEP, 0.),
+                    VirtualKeyCode::Right => self.orbit(-KEY_ORBIT_STEP, 0.),
+                    VirtualKeyCode::Up => self.orbit(0., KEY_ORBIT_STEP),
+                    VirtualKeyCode::Down => self.orbit(0., -KEY_ORBIT_STEP),
+                    VirtualKeyCode::P => self.set_projection(match self.projection {
+                        Projection::Orthographic => Projection::Perspective,
+                        Projection::Perspective => Projection::Orthographic,
+                    }),
+                    _ => return false,
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The view-projection matrix, corrected for wgpu's clip space.
+    pub fn matrix(&self) -> Matrix4<f32> {
+        let near = 0.1;
+        let far = self.distance + 100.;
+
+        let mx_projection: Matrix4<f32> = match self.projection {
+            Projection::Orthographic => {
+                let fac = 1. / (2. * self.zoom);
+                let h_width = self.width * fac;
+                let h_height = self.height * fac;
+                cgmath::Ortho {
+                    left: -h_width,
+                    right: h_width,
+                    bottom: -h_height,
+                    top: h_height,
+                    near,
+                    far,
+                }
+                .into()
+            }
+            Projection::Perspective => {
+                cgmath::perspective(self.fovy(), self.width / self.height, near, far)
+            }
+        };
+
+        let mx_view = Matrix4::look_at(self.eye(), self.target, Vector3::unit_z());
+        let mx_correction = crate::framework::OPENGL_TO_WGPU_MATRIX;
+        mx_correction * mx_projection * mx_view
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_size_keeps_the_projection() {
+        let mut camera = Camera::new(640, 480);
+        camera.set_projection(Projection::Perspective);
+        camera.resize(640, 0);
+        let matrix: [[f32; 4]; 4] = camera.matrix().into();
+        assert!(matrix.iter().flatten().all(|value| value.is_finite()));
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut camera = Camera::new(640, 480);
+        camera.scroll(-1000.);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+        camera.set_projection(Projection::Perspective);
+        camera.scroll(1000.);
+        assert_eq!(camera.distance, MIN_DISTANCE);
+        camera.look_at(Point3::new(0., 0., 0.), Point3::new(0., 0., 0.));
+        assert!(camera.eye().x.is_finite());
+    }
+}
@@ -0,0 +1,337 @@
+use crate::reflect;
+use std::borrow::Cow::Borrowed;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    /// Only 8 bits per channel RGBA and BGRA textures can be read back.
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// The readback buffer couldn't be mapped.
+    Map(wgpu::BufferAsyncError),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CaptureError::UnsupportedFormat(format) => write!(f, "cannot capture {:?} textures", format),
+            CaptureError::Map(err) => write!(f, "cannot map the readback buffer: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CaptureError::Map(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// An 8 bits per channel RGBA image, read back from the GPU.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// Texture to buffer copies need rows aligned to `COPY_BYTES_PER_ROW_ALIGNMENT`.
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (unpadded + align - 1) / align * align
+}
+
+fn is_bgra(format: wgpu::TextureFormat) -> Result<bool, CaptureError> {
+    match format {
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => Ok(true),
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => Ok(false),
+        _ => Err(CaptureError::UnsupportedFormat(format)),
+    }
+}
+
+/// Copies a texture into a mapped buffer and converts it to RGBA.
+/// The texture must have been created with `TextureUsage::COPY_SRC`.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Result<Image, CaptureError> {
+    let swap_red_blue = is_bgra(format)?;
+    let bytes_per_row = padded_bytes_per_row(width);
+
+    let readback_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_texture_to_buffer(
+        wgpu::TextureCopyView {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::BufferCopyView {
+            buffer: &readback_buf,
+            layout: wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row,
+                rows_per_image: 0,
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let slice = readback_buf.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    futures::executor::block_on(mapping).map_err(CaptureError::Map)?;
+
+    let mut data = Vec::with_capacity((width * height * 4) as usize);
+    {
+        let padded = slice.get_mapped_range();
+        for row in padded.chunks(bytes_per_row as usize) {
+            for pixel in row[..(width * 4) as usize].chunks(4) {
+                if swap_red_blue {
+                    data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                } else {
+                    data.extend_from_slice(pixel);
+                }
+            }
+        }
+    }
+    readback_buf.unmap();
+
+    Ok(Image {
+        width,
+        height,
+        data,
+    })
+}
+
+/// A texture frames can be rendered to, then both read back and sampled.
+pub fn create_target(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        format,
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT
+            | wgpu::TextureUsage::COPY_SRC
+            | wgpu::TextureUsage::SAMPLED,
+        label: None,
+    })
+}
+
+/// Renders a frame into an offscreen texture using `render`, and reads it back.
+pub fn render_to_image<F>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    render: F,
+) -> Result<Image, CaptureError>
+where
+    F: FnOnce(&wgpu::TextureView),
+{
+    let texture = create_target(device, width, height, format);
+    render(&texture.create_default_view());
+    read_texture(device, queue, &texture, width, height, format)
+}
+
+/// Copies a captured frame to the swap chain, so that the saved image is the presented one.
+pub struct Blit {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Blit {
+    fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+        [
+            wgpu::BindGroupLayoutEntry::new(
+                0,
+                wgpu::ShaderStage::FRAGMENT,
+                wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    component_type: wgpu::TextureComponentType::Float,
+                    dimension: wgpu::TextureViewDimension::D2,
+                },
+            ),
+            wgpu::BindGroupLayoutEntry::new(
+                1,
+                wgpu::ShaderStage::FRAGMENT,
+                wgpu::BindingType::Sampler { comparison: false },
+            ),
+        ]
+    }
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Result<Self, reflect::InterfaceError> {
+        let vs_words = reflect::spirv_words(include_bytes!("fxaa.vert.spv"))?;
+        let fs_words = reflect::spirv_words(include_bytes!("blit.frag.spv"))?;
+        reflect::validate_layouts(
+            &[&Self::layout_entries()],
+            &[
+                (wgpu::ShaderStage::VERTEX, &vs_words),
+                (wgpu::ShaderStage::FRAGMENT, &fs_words),
+            ],
+        )?;
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: Borrowed(&Self::layout_entries()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: Borrowed(&[&bind_group_layout]),
+            push_constant_ranges: Borrowed(&[]),
+        });
+        // same size on both sides, no filtering needed
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let vs_module = device.create_shader_module(wgpu::ShaderModuleSource::SpirV(Borrowed(&vs_words)));
+        let fs_module = device.create_shader_module(wgpu::ShaderModuleSource::SpirV(Borrowed(&fs_words)));
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout: &pipeline_layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: &vs_module,
+                entry_point: Borrowed("main"),
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: &fs_module,
+                entry_point: Borrowed("main"),
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                ..Default::default()
+            }),
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            color_states: Borrowed(&[wgpu::ColorStateDescriptor {
+                format,
+                color_blend: wgpu::BlendDescriptor::REPLACE,
+                alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }]),
+            depth_stencil_state: None,
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: Borrowed(&[]),
+            },
+            sample_count: 1,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        });
+
+        Ok(Blit {
+            bind_group_layout,
+            sampler,
+            pipeline,
+        })
+    }
+
+    pub fn render(
+        &self,
+        dest: &wgpu::TextureView,
+        source: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: Borrowed(&[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ]),
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: Borrowed(&[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: dest,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }]),
+                depth_stencil_attachment: None,
+            });
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, &bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+impl Image {
+    /// Writes a binary (P6) PPM. The alpha channel is dropped.
+    pub fn save_ppm(&self, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in self.data.chunks(4) {
+            file.write_all(&pixel[..3])?;
+        }
+        file.flush()
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        Ok(())
+    }
+
+    /// Picks the file format from the extension, defaulting to PNG.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ppm") => self.save_ppm(path),
+            _ => self.save_png(path),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Time sources and the fixed timestep logic feeding `Example::tick`.
+
+use crate::framework::Example;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(not(target_arch = "wasm32"))]
+type Timestamp = Instant;
+/// Milliseconds, from `performance.now()`.
+#[cfg(target_arch = "wasm32")]
+type Timestamp = f64;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> Timestamp {
+    Instant::now()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now() -> Timestamp {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or(0., |performance| performance.now())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn since(last: Timestamp, now: Timestamp) -> Duration {
+    now - last
+}
+
+#[cfg(target_arch = "wasm32")]
+fn since(last: Timestamp, now: Timestamp) -> Duration {
+    Duration::from_secs_f64((now - last).max(0.) / 1000.)
+}
+
+/// Fixed steps run per frame before giving up on catching up,
+/// so a slow frame doesn't snowball into slower and slower frames.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+pub trait Clock {
+    /// Time elapsed since the previous call, or since creation.
+    fn elapsed(&mut self) -> Duration;
+
+    /// Forgets the time elapsed so far, so that idle time isn't counted.
+    fn reset(&mut self) {}
+}
+
+/// Wall clock time. On the web, `performance.now()` stands in for `Instant`.
+pub struct RealClock {
+    last: Timestamp,
+}
+
+impl RealClock {
+    pub fn new() -> Self {
+        RealClock { last: now() }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn elapsed(&mut self) -> Duration {
+        let now = now();
+        let elapsed = since(self.last, now);
+        self.last = now;
+        elapsed
+    }
+
+    fn reset(&mut self) {
+        self.last = now();
+    }
+}
+
+/// Pretends each frame took exactly `step`, for reproducible runs.
+pub struct FakeClock {
+    pub step: Duration,
+}
+
+impl FakeClock {
+    pub fn new(step: Duration) -> Self {
+        FakeClock { step }
+    }
+}
+
+impl Clock for FakeClock {
+    fn elapsed(&mut self) -> Duration {
+        self.step
+    }
+}
+
+/// Turns clock time into `Example::tick` calls, either once per frame with
+/// the frame time, or at the fixed rate given by `Example::fixed_timestep`.
+pub struct Timestep {
+    clock: Box<dyn Clock>,
+    fixed: Option<Duration>,
+    accumulator: Duration,
+}
+
+impl Timestep {
+    pub fn new(clock: Box<dyn Clock>, fixed: Option<Duration>) -> Self {
+        Timestep {
+            clock,
+            fixed,
+            accumulator: Duration::from_secs(0),
+        }
+    }
+
+    /// Call when frames resume after a pause, so that the next step doesn't span it.
+    pub fn reset(&mut self) {
+        self.clock.reset();
+        self.accumulator = Duration::from_secs(0);
+    }
+
+    /// Call once per frame, before rendering.
+    pub fn advance<E: Example>(&mut self, example: &mut E) {
+        self.run(|dt| example.tick(dt))
+    }
+
+    fn run<F: FnMut(Duration)>(&mut self, mut tick: F) {
+        let dt = self.clock.elapsed();
+        let step = match self.fixed {
+            Some(step) if step > Duration::from_secs(0) => step,
+            _ => return tick(dt),
+        };
+
+        self.accumulator += dt;
+        let mut steps = 0;
+        while self.accumulator >= step {
+            if steps == MAX_STEPS_PER_FRAME {
+                log::warn!("Fixed timestep can't keep up, skipping {:?}", self.accumulator);
+                self.accumulator = Duration::from_secs(0);
+                break;
+            }
+            tick(step);
+            self.accumulator -= step;
+            steps += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs one frame, returning the steps it ticked.
+    fn frame(timestep: &mut Timestep) -> Vec<Duration> {
+        let mut ticks = Vec::new();
+        timestep.run(|dt| ticks.push(dt));
+        ticks
+    }
+
+    #[test]
+    fn fixed_steps_per_frame() {
+        let mut timestep = Timestep::new(
+            Box::new(FakeClock::new(Duration::from_millis(35))),
+            Some(Duration::from_millis(10)),
+        );
+        assert_eq!(frame(&mut timestep), vec![Duration::from_millis(10); 3]);
+        assert_eq!(timestep.accumulator, Duration::from_millis(5));
+        // the 5 ms left over complete a fourth step on the next frame
+        assert_eq!(frame(&mut timestep).len(), 4);
+        assert_eq!(timestep.accumulator, Duration::from_millis(0));
+    }
+
+    #[test]
+    fn variable_step_without_a_fixed_rate() {
+        let mut timestep = Timestep::new(Box::new(FakeClock::new(Duration::from_millis(35))), None);
+        assert_eq!(frame(&mut timestep), vec![Duration::from_millis(35)]);
+    }
+
+    #[test]
+    fn stalls_are_capped() {
+        let mut timestep = Timestep::new(
+            Box::new(FakeClock::new(Duration::from_secs(1))),
+            Some(Duration::from_millis(10)),
+        );
+        assert_eq!(frame(&mut timestep).len(), MAX_STEPS_PER_FRAME as usize);
+        assert_eq!(timestep.accumulator, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn reset_drops_the_accumulated_time() {
+        let mut timestep = Timestep::new(
+            Box::new(FakeClock::new(Duration::from_millis(15))),
+            Some(Duration::from_millis(10)),
+        );
+        timestep.accumulator = Duration::from_millis(5);
+        timestep.reset();
+        assert_eq!(timestep.accumulator, Duration::from_secs(0));
+    }
+}
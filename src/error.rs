@@ -0,0 +1,122 @@
+//! Errors setting up and running an `Example`.
+
+#[cfg(feature = "headless")]
+use crate::capture::CaptureError;
+use crate::graph::GraphError;
+use crate::reflect::InterfaceError;
+#[cfg(feature = "hot-reload")]
+use crate::shaders::ShaderError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum AnimaError {
+    /// No adapter on the requested backends, or able to present to the window.
+    NoAdapter,
+    /// No adapter whose name contains this one, from `AdapterSelection::name`.
+    AdapterNotFound(String),
+    /// Features the example requires but the adapter lacks.
+    MissingFeatures(wgpu::Features),
+    /// Names of the limits the adapter doesn't reach.
+    MissingLimits(Vec<&'static str>),
+    RequestDevice(wgpu::RequestDeviceError),
+    #[cfg(feature = "winit-runner")]
+    Window(winit::error::OsError),
+    SwapChain(wgpu::SwapChainError),
+    /// The example doesn't implement `Example::render_view`.
+    OffscreenUnsupported,
+    Shader(InterfaceError),
+    Graph(GraphError),
+    #[cfg(feature = "headless")]
+    Capture(CaptureError),
+    #[cfg(feature = "hot-reload")]
+    ShaderCompile(ShaderError),
+}
+
+impl fmt::Display for AnimaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimaError::NoAdapter => write!(f, "no suitable graphics adapter found"),
+            AnimaError::AdapterNotFound(name) => write!(f, "no graphics adapter named {:?} found", name),
+            AnimaError::MissingFeatures(features) => {
+                write!(f, "the adapter does not support the required features {:?}", features)
+            }
+            AnimaError::MissingLimits(limits) => {
+                write!(f, "the adapter does not reach the required limits: {}", limits.join(", "))
+            }
+            AnimaError::RequestDevice(err) => write!(f, "{}", err),
+            #[cfg(feature = "winit-runner")]
+            AnimaError::Window(err) => write!(f, "cannot create the window: {}", err),
+            AnimaError::SwapChain(err) => write!(f, "swap chain error: {}", err),
+            AnimaError::OffscreenUnsupported => write!(f, "the example cannot render offscreen"),
+            AnimaError::Shader(err) => write!(f, "{}", err),
+            AnimaError::Graph(err) => write!(f, "invalid render graph: {}", err),
+            #[cfg(feature = "headless")]
+            AnimaError::Capture(err) => write!(f, "{}", err),
+            #[cfg(feature = "hot-reload")]
+            AnimaError::ShaderCompile(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for AnimaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AnimaError::RequestDevice(err) => Some(err),
+            #[cfg(feature = "winit-runner")]
+            AnimaError::Window(err) => Some(err),
+            AnimaError::SwapChain(err) => Some(err),
+            AnimaError::Shader(err) => Some(err),
+            AnimaError::Graph(err) => Some(err),
+            #[cfg(feature = "headless")]
+            AnimaError::Capture(err) => Some(err),
+            #[cfg(feature = "hot-reload")]
+            AnimaError::ShaderCompile(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for AnimaError {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        AnimaError::RequestDevice(err)
+    }
+}
+
+#[cfg(feature = "winit-runner")]
+impl From<winit::error::OsError> for AnimaError {
+    fn from(err: winit::error::OsError) -> Self {
+        AnimaError::Window(err)
+    }
+}
+
+impl From<wgpu::SwapChainError> for AnimaError {
+    fn from(err: wgpu::SwapChainError) -> Self {
+        AnimaError::SwapChain(err)
+    }
+}
+
+impl From<InterfaceError> for AnimaError {
+    fn from(err: InterfaceError) -> Self {
+        AnimaError::Shader(err)
+    }
+}
+
+impl From<GraphError> for AnimaError {
+    fn from(err: GraphError) -> Self {
+        AnimaError::Graph(err)
+    }
+}
+
+#[cfg(feature = "headless")]
+impl From<CaptureError> for AnimaError {
+    fn from(err: CaptureError) -> Self {
+        AnimaError::Capture(err)
+    }
+}
+
+#[cfg(feature = "hot-reload")]
+impl From<ShaderError> for AnimaError {
+    fn from(err: ShaderError) -> Self {
+        AnimaError::ShaderCompile(err)
+    }
+}
@@ -1,8 +1,22 @@
+#[cfg(feature = "winit-runner")]
+use crate::adapter;
+use crate::adapter::AdapterSelection;
+#[cfg(feature = "winit-runner")]
+use crate::clock::{Clock, RealClock, Timestep};
+#[cfg(all(feature = "winit-runner", feature = "headless", not(target_arch = "wasm32")))]
+use crate::capture;
+use crate::error::AnimaError;
 use futures::task::LocalSpawn;
-#[cfg(not(target_arch = "wasm32"))]
-use std::time::{Duration, Instant};
+use std::time::Duration;
+#[cfg(all(feature = "winit-runner", not(target_arch = "wasm32")))]
+use std::time::Instant;
+#[cfg(feature = "winit-runner")]
+use crate::builders::SwapChainDescBuilder;
+#[cfg(feature = "winit-runner")]
+use winit::event::WindowEvent;
+#[cfg(feature = "winit-runner")]
 use winit::{
-    event::{self, WindowEvent},
+    event,
     event_loop::{ControlFlow, EventLoop},
 };
 
@@ -29,6 +43,68 @@ pub enum ShaderStage {
     Compute,
 }
 
+/// When frames get rendered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FramePacing {
+    /// As fast as possible, or as fast as the present mode allows.
+    Uncapped,
+    /// At most this many frames per second.
+    TargetFps(u32),
+    /// Only after input, resizes, or when `Example::wants_redraw` says so.
+    OnDemand,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FrameConfig {
+    /// Only `Fifo` is guaranteed to be supported.
+    /// wgpu falls back to it when the requested mode isn't.
+    pub present_mode: wgpu::PresentMode,
+    pub pacing: FramePacing,
+}
+
+impl Default for FrameConfig {
+    fn default() -> Self {
+        FrameConfig {
+            present_mode: wgpu::PresentMode::Fifo,
+            pacing: FramePacing::Uncapped,
+        }
+    }
+}
+
+impl FrameConfig {
+    /// Applies the `ANIMA_PRESENT_MODE` (fifo, mailbox, immediate) and
+    /// `ANIMA_FPS` (a number, uncapped, on-demand) environment variables.
+    pub fn with_env_overrides(mut self) -> Self {
+        if let Ok(mode) = std::env::var("ANIMA_PRESENT_MODE") {
+            match mode.to_lowercase().as_str() {
+                "fifo" => self.present_mode = wgpu::PresentMode::Fifo,
+                "mailbox" => self.present_mode = wgpu::PresentMode::Mailbox,
+                "immediate" => self.present_mode = wgpu::PresentMode::Immediate,
+                _ => log::warn!("Ignoring unknown ANIMA_PRESENT_MODE: {}", mode),
+            }
+        }
+        if let Ok(fps) = std::env::var("ANIMA_FPS") {
+            match fps.to_lowercase().as_str() {
+                "uncapped" => self.pacing = FramePacing::Uncapped,
+                "on-demand" => self.pacing = FramePacing::OnDemand,
+                number => match number.parse() {
+                    Ok(fps) => self.pacing = FramePacing::TargetFps(fps),
+                    Err(_) => log::warn!("Ignoring invalid ANIMA_FPS: {}", fps),
+                },
+            }
+        }
+        self
+    }
+
+    /// The minimum time between two frames, if any.
+    pub fn frame_interval(&self) -> Option<Duration> {
+        match self.pacing {
+            FramePacing::TargetFps(fps) if fps > 0 => Some(Duration::from_secs(1) / fps),
+            _ => None,
+        }
+    }
+}
+
 pub trait Example: 'static + Sized {
     fn optional_features() -> wgpu::Features {
         wgpu::Features::empty()
@@ -39,18 +115,40 @@ pub trait Example: 'static + Sized {
     fn required_limits() -> wgpu::Limits {
         wgpu::Limits::default()
     }
+    fn frame_config() -> FrameConfig {
+        FrameConfig::default()
+    }
+    /// Overridden by the environment and the command line, see `AdapterSelection`.
+    fn adapter_selection() -> AdapterSelection {
+        AdapterSelection::default()
+    }
+    /// Errors end the run, and are returned by `run` and `headless::run`.
     fn init(
         sc_desc: &wgpu::SwapChainDescriptor,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-    ) -> Self;
+    ) -> Result<Self, AnimaError>;
+    /// Called once after `init`, with the adapter the device was created from.
+    fn adapter_info(&mut self, _info: &wgpu::AdapterInfo) {}
     fn resize(
         &mut self,
         sc_desc: &wgpu::SwapChainDescriptor,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
     );
+    #[cfg(feature = "winit-runner")]
     fn update(&mut self, event: WindowEvent);
+    /// Advances the simulation by `dt`. Called before each `render`, or at a fixed
+    /// rate if `fixed_timestep` returns a step.
+    fn tick(&mut self, _dt: Duration) {}
+    fn fixed_timestep() -> Option<Duration> {
+        None
+    }
+    /// Whether there is something new to show. Only used with `FramePacing::OnDemand`,
+    /// where input events and resizes always trigger a redraw.
+    fn wants_redraw(&self) -> bool {
+        false
+    }
     fn render(
         &mut self,
         frame: &wgpu::SwapChainTexture,
@@ -58,8 +156,19 @@ pub trait Example: 'static + Sized {
         queue: &wgpu::Queue,
         spawner: &impl LocalSpawn,
     );
+    /// Renders to any view of the swap chain format, for the headless runner and captures.
+    fn render_view(
+        &mut self,
+        _view: &wgpu::TextureView,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _spawner: &impl LocalSpawn,
+    ) -> Result<(), AnimaError> {
+        Err(AnimaError::OffscreenUnsupported)
+    }
 }
 
+#[cfg(feature = "winit-runner")]
 struct Setup {
     window: winit::window::Window,
     event_loop: EventLoop<()>,
@@ -72,7 +181,96 @@ struct Setup {
     queue: wgpu::Queue,
 }
 
-async fn setup<E: Example>(title: &str) -> Setup {
+/// Names of the limits in `required` which are above the `supported` ones.
+pub fn missing_limits(required: &wgpu::Limits, supported: &wgpu::Limits) -> Vec<&'static str> {
+    let limits = [
+        ("max_bind_groups", required.max_bind_groups, supported.max_bind_groups),
+        (
+            "max_dynamic_uniform_buffers_per_pipeline_layout",
+            required.max_dynamic_uniform_buffers_per_pipeline_layout,
+            supported.max_dynamic_uniform_buffers_per_pipeline_layout,
+        ),
+        (
+            "max_dynamic_storage_buffers_per_pipeline_layout",
+            required.max_dynamic_storage_buffers_per_pipeline_layout,
+            supported.max_dynamic_storage_buffers_per_pipeline_layout,
+        ),
+        (
+            "max_sampled_textures_per_shader_stage",
+            required.max_sampled_textures_per_shader_stage,
+            supported.max_sampled_textures_per_shader_stage,
+        ),
+        (
+            "max_samplers_per_shader_stage",
+            required.max_samplers_per_shader_stage,
+            supported.max_samplers_per_shader_stage,
+        ),
+        (
+            "max_storage_buffers_per_shader_stage",
+            required.max_storage_buffers_per_shader_stage,
+            supported.max_storage_buffers_per_shader_stage,
+        ),
+        (
+            "max_storage_textures_per_shader_stage",
+            required.max_storage_textures_per_shader_stage,
+            supported.max_storage_textures_per_shader_stage,
+        ),
+        (
+            "max_uniform_buffers_per_shader_stage",
+            required.max_uniform_buffers_per_shader_stage,
+            supported.max_uniform_buffers_per_shader_stage,
+        ),
+        (
+            "max_uniform_buffer_binding_size",
+            required.max_uniform_buffer_binding_size,
+            supported.max_uniform_buffer_binding_size,
+        ),
+        (
+            "max_push_constant_size",
+            required.max_push_constant_size,
+            supported.max_push_constant_size,
+        ),
+    ];
+    limits
+        .iter()
+        .filter(|(_, required, supported)| required > supported)
+        .map(|&(name, _, _)| name)
+        .collect()
+}
+
+#[cfg(any(feature = "winit-runner", feature = "headless"))]
+pub(crate) async fn request_device<E: Example>(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), AnimaError> {
+    let optional_features = E::optional_features();
+    let required_features = E::required_features();
+    let adapter_features = adapter.features();
+    if !adapter_features.contains(required_features) {
+        return Err(AnimaError::MissingFeatures(required_features - adapter_features));
+    }
+
+    let needed_limits = E::required_limits();
+    let missing = missing_limits(&needed_limits, &adapter.limits());
+    if !missing.is_empty() {
+        return Err(AnimaError::MissingLimits(missing));
+    }
+
+    let trace_dir = std::env::var("WGPU_TRACE");
+    let device = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: (optional_features & adapter_features) | required_features,
+                limits: needed_limits,
+                shader_validation: true,
+            },
+            trace_dir.ok().as_ref().map(std::path::Path::new),
+        )
+        .await?;
+    Ok(device)
+}
+
+#[cfg(feature = "winit-runner")]
+async fn setup<E: Example>(title: &str, selection: &AdapterSelection) -> Result<Setup, AnimaError> {
     #[cfg(all(not(target_arch = "wasm32"), feature = "subscriber"))]
     {
         let chrome_tracing_dir = std::env::var("WGPU_CHROME_TRACE");
@@ -92,11 +290,11 @@ async fn setup<E: Example>(title: &str) -> Setup {
         use winit::platform::windows::WindowBuilderExtWindows;
         builder = builder.with_no_redirection_bitmap(true);
     }
-    let window = builder.build(&event_loop).unwrap();
+    let window = builder.build(&event_loop)?;
 
     log::info!("Initializing the surface...");
 
-    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    let instance = wgpu::Instance::new(selection.backends);
     let (size, scale_factor, surface) = unsafe {
         let size = window.inner_size();
         let scale = window.scale_factor();
@@ -104,39 +302,11 @@ async fn setup<E: Example>(title: &str) -> Setup {
         (size, scale, surface)
     };
 
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::Default,
-            compatible_surface: Some(&surface),
-        })
-        .await
-        .unwrap();
-
-    let optional_features = E::optional_features();
-    let required_features = E::required_features();
-    let adapter_features = adapter.features();
-    assert!(
-        adapter_features.contains(required_features),
-        "Adapter does not support required features for this example: {:?}",
-        required_features - adapter_features
-    );
-
-    let needed_limits = E::required_limits();
+    let adapter = adapter::request_adapter(&instance, selection, Some(&surface)).await?;
+    let (device, queue) = request_device::<E>(&adapter).await?;
+    adapter::report(&adapter, &device);
 
-    let trace_dir = std::env::var("WGPU_TRACE");
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                features: (optional_features & adapter_features) | required_features,
-                limits: needed_limits,
-                shader_validation: true,
-            },
-            trace_dir.ok().as_ref().map(std::path::Path::new),
-        )
-        .await
-        .unwrap();
-
-    Setup {
+    Ok(Setup {
         window,
         event_loop,
         instance,
@@ -146,9 +316,10 @@ async fn setup<E: Example>(title: &str) -> Setup {
         adapter,
         device,
         queue,
-    }
+    })
 }
 
+#[cfg(feature = "winit-runner")]
 fn start<E: Example>(
     Setup {
         window,
@@ -161,7 +332,8 @@ fn start<E: Example>(
         device,
         queue,
     }: Setup,
-) {
+    clock: Box<dyn Clock>,
+) -> Result<(), AnimaError> {
     #[cfg(not(target_arch = "wasm32"))]
     let (mut pool, spawner) = {
         let local_pool = futures::executor::LocalPool::new();
@@ -199,25 +371,49 @@ fn start<E: Example>(
         WebSpawner {}
     };
 
-    let mut sc_desc = wgpu::SwapChainDescriptor {
-        usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+    let frame_config = E::frame_config().with_env_overrides();
+    log::info!("Frame config: {:?}", frame_config);
+    if frame_config.present_mode != wgpu::PresentMode::Fifo {
+        log::warn!(
+            "Present mode {:?} may not be supported, in which case wgpu falls back to Fifo",
+            frame_config.present_mode
+        );
+    }
+
+    let mut sc_desc = SwapChainDescBuilder::new(size.width, size.height, wgpu::TextureUsage::OUTPUT_ATTACHMENT)
         // TODO: Allow srgb unconditionally
-        format: if cfg!(target_arch = "wasm32") {
+        .format(if cfg!(target_arch = "wasm32") {
             wgpu::TextureFormat::Bgra8Unorm
         } else {
             wgpu::TextureFormat::Bgra8UnormSrgb
-        },
-        width: size.width,
-        height: size.height,
-        present_mode: wgpu::PresentMode::Mailbox,
-    };
+        })
+        .present_mode(frame_config.present_mode)
+        .build();
     let mut swap_chain = device.create_swap_chain(&surface, &sc_desc);
 
     log::info!("Initializing the example...");
-    let mut example = E::init(&sc_desc, &device, &queue);
+    let mut example = E::init(&sc_desc, &device, &queue)?;
+    example.adapter_info(&adapter.get_info());
 
     #[cfg(not(target_arch = "wasm32"))]
     let mut last_update_inst = Instant::now();
+    #[cfg(not(target_arch = "wasm32"))]
+    let frame_interval = frame_config.frame_interval();
+    // in on-demand mode, whether something happened since the last frame
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut redraw_pending = true;
+    // in on-demand mode, whether frames stopped, so the clock kept running meanwhile
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut idle = false;
+    let mut timestep = Timestep::new(clock, E::fixed_timestep());
+
+    // F12 renders the next frame offscreen, then shows and saves it
+    #[cfg(all(feature = "headless", not(target_arch = "wasm32")))]
+    let mut capture_pending = false;
+    #[cfg(all(feature = "headless", not(target_arch = "wasm32")))]
+    let mut capture_count = 0;
+    #[cfg(all(feature = "headless", not(target_arch = "wasm32")))]
+    let mut blit = None;
 
     log::info!("Entering render loop...");
     event_loop.run(move |event, _, control_flow| {
@@ -227,7 +423,15 @@ fn start<E: Example>(
         } else {
             #[cfg(not(target_arch = "wasm32"))]
             {
-                ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(10))
+                match frame_config.pacing {
+                    FramePacing::Uncapped => ControlFlow::Poll,
+                    FramePacing::TargetFps(_) => match frame_interval {
+                        Some(interval) => ControlFlow::WaitUntil(last_update_inst + interval),
+                        None => ControlFlow::Poll,
+                    },
+                    FramePacing::OnDemand if redraw_pending || example.wants_redraw() => ControlFlow::Poll,
+                    FramePacing::OnDemand => ControlFlow::Wait,
+                }
             }
             #[cfg(target_arch = "wasm32")]
             {
@@ -238,9 +442,24 @@ fn start<E: Example>(
             event::Event::MainEventsCleared => {
                 #[cfg(not(target_arch = "wasm32"))]
                 {
-                    if last_update_inst.elapsed() > Duration::from_millis(20) {
+                    let redraw = match frame_config.pacing {
+                        FramePacing::Uncapped => true,
+                        FramePacing::TargetFps(_) => match frame_interval {
+                            Some(interval) => last_update_inst.elapsed() >= interval,
+                            None => true,
+                        },
+                        FramePacing::OnDemand => redraw_pending || example.wants_redraw(),
+                    };
+                    if redraw {
+                        if idle {
+                            timestep.reset();
+                            idle = false;
+                        }
                         window.request_redraw();
                         last_update_inst = Instant::now();
+                        redraw_pending = false;
+                    } else {
+                        idle = frame_config.pacing == FramePacing::OnDemand;
                     }
 
                     pool.run_until_stalled();
@@ -258,6 +477,10 @@ fn start<E: Example>(
                 sc_desc.height = size.height;
                 example.resize(&sc_desc, &device, &queue);
                 swap_chain = device.create_swap_chain(&surface, &sc_desc);
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    redraw_pending = true;
+                }
             }
             event::Event::WindowEvent {
                 event: WindowEvent::ScaleFactorChanged { scale_factor: new_scale_factor, new_inner_size },
@@ -269,6 +492,10 @@ fn start<E: Example>(
                 scale_factor = new_scale_factor;
                 example.resize(&sc_desc, &device, &queue);
                 swap_chain = device.create_swap_chain(&surface, &sc_desc);
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    redraw_pending = true;
+                }
             }
             event::Event::WindowEvent { event, .. } => match event {
                 WindowEvent::KeyboardInput {
@@ -283,8 +510,25 @@ fn start<E: Example>(
                 | WindowEvent::CloseRequested => {
                     *control_flow = ControlFlow::Exit;
                 }
+                #[cfg(all(feature = "headless", not(target_arch = "wasm32")))]
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::F12),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    capture_pending = true;
+                    window.request_redraw();
+                }
                 _ => {
                     example.update(event);
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        redraw_pending = true;
+                    }
                 }
             },
             event::Event::RedrawRequested(_) => {
@@ -292,12 +536,54 @@ fn start<E: Example>(
                     Ok(frame) => frame,
                     Err(_) => {
                         swap_chain = device.create_swap_chain(&surface, &sc_desc);
-                        swap_chain
-                            .get_current_frame()
-                            .expect("Failed to acquire next swap chain texture!")
+                        match swap_chain.get_current_frame() {
+                            Ok(frame) => frame,
+                            Err(err) => {
+                                log::error!("{}", AnimaError::from(err));
+                                *control_flow = ControlFlow::Exit;
+                                return;
+                            }
+                        }
                     }
                 };
 
+                timestep.advance(&mut example);
+
+                #[cfg(all(feature = "headless", not(target_arch = "wasm32")))]
+                {
+                    if capture_pending {
+                        capture_pending = false;
+                        let captured = capture_frame(
+                            &mut example,
+                            &frame.output,
+                            &mut blit,
+                            &sc_desc,
+                            &device,
+                            &queue,
+                            &spawner,
+                        );
+                        match captured {
+                            Ok(image) => {
+                                capture_count += 1;
+                                let timestamp = std::time::SystemTime::now()
+                                    .duration_since(std::time::UNIX_EPOCH)
+                                    .map(|elapsed| elapsed.as_millis())
+                                    .unwrap_or(0);
+                                let path = format!("screenshot-{}-{}.png", timestamp, capture_count);
+                                match image.save(std::path::Path::new(&path)) {
+                                    Ok(()) => log::info!("Saved screenshot to {}", path),
+                                    Err(err) => log::error!("Failed to save screenshot to {}: {}", path, err),
+                                }
+                            }
+                            Err(err) => {
+                                log::error!("Cannot take a screenshot: {}", err);
+                                example.render(&frame.output, &device, &queue, &spawner);
+                            }
+                        }
+                        return;
+                    }
+                }
+
                 example.render(&frame.output, &device, &queue, &spawner);
             }
             _ => {}
@@ -305,17 +591,93 @@ fn start<E: Example>(
     });
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub fn run<E: Example>(title: &str) {
-    let setup = futures::executor::block_on(setup::<E>(title));
-    start::<E>(setup);
+/// Renders a frame of `example` through `Example::render_view`, copies it to `frame`
+/// and reads it back, so that the image matches what is presented.
+#[cfg(all(feature = "winit-runner", feature = "headless", not(target_arch = "wasm32")))]
+fn capture_frame<E: Example>(
+    example: &mut E,
+    frame: &wgpu::SwapChainTexture,
+    blit: &mut Option<capture::Blit>,
+    sc_desc: &wgpu::SwapChainDescriptor,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    spawner: &impl LocalSpawn,
+) -> Result<capture::Image, AnimaError> {
+    let target = capture::create_target(device, sc_desc.width, sc_desc.height, sc_desc.format);
+    let target_view = target.create_default_view();
+    example.render_view(&target_view, device, queue, spawner)?;
+    if blit.is_none() {
+        *blit = Some(capture::Blit::new(device, sc_desc.format)?);
+    }
+    if let Some(blit) = blit {
+        blit.render(&frame.view, &target_view, device, queue);
+    }
+    Ok(capture::read_texture(
+        device,
+        queue,
+        &target,
+        sc_desc.width,
+        sc_desc.height,
+        sc_desc.format,
+    )?)
+}
+
+/// Opens a window and runs `E` until it is closed. Only returns on setup errors,
+/// including those of `Example::init`, after which another backend may be tried,
+/// or after listing the adapters.
+#[cfg(feature = "winit-runner")]
+pub fn run<E: Example>(title: &str) -> Result<(), AnimaError> {
+    run_with_clock::<E>(title, Box::new(RealClock::new()))
+}
+
+/// Like `run`, with `clock` driving `Example::tick` instead of the wall clock.
+#[cfg(all(feature = "winit-runner", not(target_arch = "wasm32")))]
+pub fn run_with_clock<E: Example>(title: &str, clock: Box<dyn Clock>) -> Result<(), AnimaError> {
+    let selection = E::adapter_selection()
+        .with_env_overrides()
+        .with_args(std::env::args().skip(1));
+    if selection.list {
+        let adapters = adapter::list_adapters(&wgpu::Instance::new(selection.backends), selection.backends);
+        if adapters.is_empty() {
+            log::info!("No adapter found on {:?}", selection.backends);
+        }
+        for adapter in adapters {
+            log::info!("{}", adapter);
+        }
+        return Ok(());
+    }
+    let setup = futures::executor::block_on(setup::<E>(title, &selection))?;
+    start::<E>(setup, clock)
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn run<E: Example>(title: &str) {
+/// Setup happens asynchronously, so its errors are only logged.
+#[cfg(all(feature = "winit-runner", target_arch = "wasm32"))]
+pub fn run_with_clock<E: Example>(title: &str, clock: Box<dyn Clock>) -> Result<(), AnimaError> {
     let title = title.to_owned();
+    let selection = E::adapter_selection();
     wasm_bindgen_futures::spawn_local(async move {
-        let setup = setup::<E>(&title).await;
-        start::<E>(setup);
+        let result = setup::<E>(&title, &selection)
+            .await
+            .and_then(|setup| start::<E>(setup, clock));
+        if let Err(err) = result {
+            log::error!("{}", err);
+        }
     });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_above_supported() {
+        let supported = wgpu::Limits::default();
+        let required = wgpu::Limits {
+            max_bind_groups: supported.max_bind_groups + 1,
+            max_samplers_per_shader_stage: supported.max_samplers_per_shader_stage - 1,
+            ..wgpu::Limits::default()
+        };
+        assert_eq!(missing_limits(&required, &supported), vec!["max_bind_groups"]);
+    }
 }
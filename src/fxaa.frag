@@ -31,6 +31,10 @@ layout(location = 0) in INTERFACE {
 layout(set = 0, binding = 0) uniform texture2D t_screenTexture; ///< Image to filter.
 layout(set = 0, binding = 1) uniform Locals {
     vec2 pixel_size; ///< Size of one-pixel in UV space.
+    float edge_threshold_min;
+    float edge_threshold_max;
+    float subpixel_quality;
+    int iterations;
 };
 
 layout(set = 1, binding = 0) uniform sampler s_screenTexture;
@@ -39,12 +43,12 @@ layout(set = 1, binding = 0) uniform sampler s_screenTexture;
 #define screenTexture sampler2D(t_screenTexture, s_screenTexture)
 
 
-// Settings for FXAA.
-#define EDGE_THRESHOLD_MIN 0.0312
-#define EDGE_THRESHOLD_MAX 0.125
+// Settings for FXAA, see FxaaSettings.
+#define EDGE_THRESHOLD_MIN edge_threshold_min
+#define EDGE_THRESHOLD_MAX edge_threshold_max
 #define QUALITY(q) ((q) < 5 ? 1.0 : ((q) > 5 ? ((q) < 10 ? 2.0 : ((q) < 11 ? 4.0 : 8.0)) : 1.5))
-#define ITERATIONS 12
-#define SUBPIXEL_QUALITY 0.75
+#define ITERATIONS iterations
+#define SUBPIXEL_QUALITY subpixel_quality
 
 // Output: the fragment color
 layout(location = 0) out vec4 fragColor; ///< Color.
@@ -1,36 +1,126 @@
+use crate::postprocess::PostProcessPass;
+use crate::reflect;
+use bytemuck::{Pod, Zeroable};
 use std::borrow::Cow::Borrowed;
 use wgpu::util::DeviceExt;
 
+/// FXAA tuning knobs. See the presets for sensible values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FxaaSettings {
+    /// Edges with a luma contrast below this are ignored.
+    pub edge_threshold_min: f32,
+    /// Edges with a luma contrast below this fraction of the local maximum luma are ignored.
+    pub edge_threshold_max: f32,
+    /// How much sub-pixel aliasing is removed, from 0 (none) to 1 (softer).
+    pub subpixel_quality: f32,
+    /// Maximum number of steps taken along an edge to find its end.
+    pub iterations: u32,
+}
+
+impl FxaaSettings {
+    pub fn low() -> Self {
+        FxaaSettings {
+            edge_threshold_min: 0.0833,
+            edge_threshold_max: 0.250,
+            subpixel_quality: 0.50,
+            iterations: 5,
+        }
+    }
+
+    pub fn medium() -> Self {
+        FxaaSettings {
+            edge_threshold_min: 0.0625,
+            edge_threshold_max: 0.166,
+            subpixel_quality: 0.75,
+            iterations: 8,
+        }
+    }
+
+    pub fn high() -> Self {
+        FxaaSettings {
+            edge_threshold_min: 0.0312,
+            edge_threshold_max: 0.125,
+            subpixel_quality: 0.75,
+            iterations: 12,
+        }
+    }
+
+    pub fn ultra() -> Self {
+        FxaaSettings {
+            edge_threshold_min: 0.0156,
+            edge_threshold_max: 0.063,
+            subpixel_quality: 1.0,
+            iterations: 12,
+        }
+    }
+}
+
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        Self::high()
+    }
+}
+
+/// Matches the std140 layout of `Locals` in fxaa.frag.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Uniforms {
+    pixel_size: [f32; 2],
+    edge_threshold_min: f32,
+    edge_threshold_max: f32,
+    subpixel_quality: f32,
+    iterations: i32,
+    _padding: [f32; 2],
+}
+
+unsafe impl Pod for Uniforms {}
+unsafe impl Zeroable for Uniforms {}
+
+impl Uniforms {
+    fn new(width: u32, height: u32, settings: &FxaaSettings) -> Self {
+        Uniforms {
+            pixel_size: [1. / (width as f32), 1. / (height as f32)],
+            edge_threshold_min: settings.edge_threshold_min,
+            edge_threshold_max: settings.edge_threshold_max,
+            subpixel_quality: settings.subpixel_quality,
+            iterations: settings.iterations as i32,
+            _padding: [0.; 2],
+        }
+    }
+}
+
 pub struct FXAAPass {
-    // volatile bind group (0)
+    // volatile bind group (0), rebuilt by `set_source`
     volatile_bind_group_layout: wgpu::BindGroupLayout,
     volatile_bind_group: Option<wgpu::BindGroup>,
+    uniform_buf: wgpu::Buffer,
+    uniforms_dirty: bool,
 
     // rarely changed bind group (1)
     bind_group: wgpu::BindGroup,
     sampler: wgpu::Sampler,
 
+    // kept to rebuild the pipeline with new shaders
+    #[cfg(feature = "hot-reload")]
+    pipeline_layout: wgpu::PipelineLayout,
     pipeline: wgpu::RenderPipeline,
+    #[cfg(feature = "hot-reload")]
+    format: wgpu::TextureFormat,
 
     width: u32,
     height: u32,
+    settings: FxaaSettings,
+
+    /// Number of bind groups created by `set_source` so far.
+    allocations: usize,
 }
 
 impl FXAAPass {
     pub fn volatile_bind_group(
-        &mut self,
+        &self,
         device: &wgpu::Device,
-        width: u32,
-        height: u32,
         source_image: &wgpu::TextureView,
     ) -> wgpu::BindGroup {
-        let pixel_size = &[1. / (width as f32), 1. / (height as f32)];
-        let pixel_size_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Pixel Size Buffer"),
-            contents: bytemuck::cast_slice(pixel_size),
-            usage: wgpu::BufferUsage::UNIFORM,
-        });
-
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some(Borrowed("Volatile bind group")),
             layout: &self.volatile_bind_group_layout,
@@ -41,50 +131,125 @@ impl FXAAPass {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Buffer(pixel_size_buf.slice(..)),
+                    resource: wgpu::BindingResource::Buffer(self.uniform_buf.slice(..)),
                 },
             ]),
         })
     }
 
-    pub fn new(format: wgpu::TextureFormat, width: u32, height: u32, device: &wgpu::Device, _queue: &wgpu::Queue) -> Self {
-        // the bind group that changes on each frame
+    fn volatile_layout_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+        [
+            // t_screenTexture: texture2D
+            wgpu::BindGroupLayoutEntry::new(
+                0,
+                wgpu::ShaderStage::FRAGMENT,
+                wgpu::BindingType::SampledTexture {
+                    // This is msaa related, and has nothing to do with the number of times the texture
+                    // is sampled inside the shader.
+                    multisampled: false,
+                    component_type: wgpu::TextureComponentType::Float,
+                    dimension: wgpu::TextureViewDimension::D2,
+                },
+            ),
+            // Locals: pixel_size and settings
+            wgpu::BindGroupLayoutEntry::new(
+                1,
+                wgpu::ShaderStage::FRAGMENT,
+                wgpu::BindingType::UniformBuffer {
+                    dynamic: false,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+                    ),
+                },
+            ),
+        ]
+    }
+
+    fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 1] {
+        [wgpu::BindGroupLayoutEntry::new(
+            0,
+            wgpu::ShaderStage::FRAGMENT,
+            wgpu::BindingType::Sampler { comparison: false },
+        )]
+    }
+
+    fn validate_shaders(vs_words: &[u32], fs_words: &[u32]) -> Result<(), reflect::InterfaceError> {
+        reflect::validate_layouts(
+            &[&Self::volatile_layout_entries(), &Self::layout_entries()],
+            &[
+                (wgpu::ShaderStage::VERTEX, vs_words),
+                (wgpu::ShaderStage::FRAGMENT, fs_words),
+            ],
+        )
+    }
+
+    /// The shaders must have gone through `validate_shaders`.
+    fn create_pipeline(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        vs_words: &[u32],
+        fs_words: &[u32],
+    ) -> wgpu::RenderPipeline {
+        let vs_module = device.create_shader_module(wgpu::ShaderModuleSource::SpirV(Borrowed(vs_words)));
+        let fs_module = device.create_shader_module(wgpu::ShaderModuleSource::SpirV(Borrowed(fs_words)));
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout: pipeline_layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: &vs_module,
+                entry_point: Borrowed("main"),
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: &fs_module,
+                entry_point: Borrowed("main"),
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                ..Default::default()
+            }),
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            color_states: Borrowed(&[wgpu::ColorStateDescriptor {
+                format,
+                color_blend: wgpu::BlendDescriptor::REPLACE,
+                alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }]),
+            depth_stencil_state: None,
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: Borrowed(&[]),
+            },
+            sample_count: 1,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        })
+    }
+
+    pub fn new(
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        settings: FxaaSettings,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+    ) -> Result<Self, reflect::InterfaceError> {
+        let vs_words = reflect::spirv_words(include_bytes!("fxaa.vert.spv"))?;
+        let fs_words = reflect::spirv_words(include_bytes!("fxaa.frag.spv"))?;
+        Self::validate_shaders(&vs_words, &fs_words)?;
+
+        // the bind group that changes when the source image does
         let volatile_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
-                entries: Borrowed(&[
-                    // t_screenTexture: texture2D
-                    wgpu::BindGroupLayoutEntry::new(
-                        0,
-                        wgpu::ShaderStage::FRAGMENT,
-                        wgpu::BindingType::SampledTexture {
-                            // This is msaa related, and has nothing to do with the number of times the texture
-                            // is sampled inside the shader.
-                            multisampled: false,
-                            component_type: wgpu::TextureComponentType::Float,
-                            dimension: wgpu::TextureViewDimension::D2Array,
-                        },
-                    ),
-                    // pixel_size: vec2
-                    wgpu::BindGroupLayoutEntry::new(
-                        1,
-                        wgpu::ShaderStage::FRAGMENT,
-                        wgpu::BindingType::UniformBuffer {
-                            dynamic: false,
-                            min_binding_size: wgpu::BufferSize::new(4 * 2),
-                        },
-                    ),
-                ]),
+                entries: Borrowed(&Self::volatile_layout_entries()),
             });
 
         // the bind group that never changes
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            entries: Borrowed(&[wgpu::BindGroupLayoutEntry::new(
-                0,
-                wgpu::ShaderStage::FRAGMENT,
-                wgpu::BindingType::Sampler { comparison: false },
-            )]),
+            entries: Borrowed(&Self::layout_entries()),
         });
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -102,6 +267,13 @@ impl FXAAPass {
             ..Default::default()
         });
 
+        let uniforms = [Uniforms::new(width, height, &settings)];
+        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("FXAA Uniform Buffer"),
+            contents: bytemuck::cast_slice(&uniforms),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
         // Create bind group
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
@@ -113,51 +285,66 @@ impl FXAAPass {
         });
 
         // Create the render pipeline
-        let vs_module = device.create_shader_module(wgpu::include_spirv!("fxaa.vert.spv"));
-        let fs_module = device.create_shader_module(wgpu::include_spirv!("fxaa.frag.spv"));
-
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            layout: &pipeline_layout,
-            vertex_stage: wgpu::ProgrammableStageDescriptor {
-                module: &vs_module,
-                entry_point: Borrowed("main"),
-            },
-            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                module: &fs_module,
-                entry_point: Borrowed("main"),
-            }),
-            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: wgpu::CullMode::None,
-                ..Default::default()
-            }),
-            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-            color_states: Borrowed(&[wgpu::ColorStateDescriptor {
-                format,
-                color_blend: wgpu::BlendDescriptor::REPLACE,
-                alpha_blend: wgpu::BlendDescriptor::REPLACE,
-                write_mask: wgpu::ColorWrite::ALL,
-            }]),
-            depth_stencil_state: None,
-            vertex_state: wgpu::VertexStateDescriptor {
-                index_format: wgpu::IndexFormat::Uint16,
-                vertex_buffers: Borrowed(&[]),
-            },
-            sample_count: 1,
-            sample_mask: !0,
-            alpha_to_coverage_enabled: false,
-        });
+        let pipeline = Self::create_pipeline(device, &pipeline_layout, format, &vs_words, &fs_words);
 
         // Done
-        FXAAPass {
+        Ok(FXAAPass {
             volatile_bind_group_layout,
             volatile_bind_group: None,
+            uniform_buf,
+            uniforms_dirty: false,
             bind_group,
             sampler,
+            #[cfg(feature = "hot-reload")]
+            pipeline_layout,
             pipeline,
+            #[cfg(feature = "hot-reload")]
+            format,
             width,
             height,
-        }
+            settings,
+            allocations: 0,
+        })
+    }
+
+    /// Number of bind groups created by `set_source` so far. This stays constant
+    /// from one frame to the next, unless the source is recreated.
+    pub fn allocations(&self) -> usize {
+        self.allocations
+    }
+
+    pub fn settings(&self) -> &FxaaSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: FxaaSettings) {
+        self.settings = settings;
+        self.uniforms_dirty = true;
+    }
+
+    /// Rebuilds the pipeline from new SPIR-V. On error, the current pipeline is kept.
+    #[cfg(feature = "hot-reload")]
+    pub fn set_shaders(
+        &mut self,
+        device: &wgpu::Device,
+        vs_words: &[u32],
+        fs_words: &[u32],
+    ) -> Result<(), reflect::InterfaceError> {
+        Self::validate_shaders(vs_words, fs_words)?;
+        self.pipeline = Self::create_pipeline(device, &self.pipeline_layout, self.format, vs_words, fs_words);
+        Ok(())
+    }
+
+    /// Sets the image `render` filters. Call it again whenever that view is recreated.
+    pub fn set_source(&mut self, source_image: &wgpu::TextureView, device: &wgpu::Device) {
+        self.volatile_bind_group = Some(self.volatile_bind_group(device, source_image));
+        self.allocations += 1;
+    }
+
+    fn write_uniforms(&mut self, queue: &wgpu::Queue) {
+        let uniforms = [Uniforms::new(self.width, self.height, &self.settings)];
+        queue.write_buffer(&self.uniform_buf, 0, bytemuck::cast_slice(&uniforms));
+        self.uniforms_dirty = false;
     }
 
     pub fn resize(
@@ -165,21 +352,31 @@ impl FXAAPass {
         width: u32,
         height: u32,
         _device: &wgpu::Device,
-        _queue: &wgpu::Queue,
+        queue: &wgpu::Queue,
     ) {
         self.width = width;
         self.height = height;
+        self.write_uniforms(queue);
     }
 
+    /// Filters the image given to `set_source` into `dest_image`. Draws nothing without one.
     pub fn render(
         &mut self,
         dest_image: &wgpu::TextureView,
-        source_image: &wgpu::TextureView,
-        device: &wgpu::Device,
-        _queue: &wgpu::Queue,
+        _device: &wgpu::Device,
+        queue: &wgpu::Queue,
         encoder: &mut wgpu::CommandEncoder,
     ) {
-        self.volatile_bind_group = Some(self.volatile_bind_group(device, self.width, self.height, source_image));
+        if self.uniforms_dirty {
+            self.write_uniforms(queue);
+        }
+        let volatile_bind_group = match &self.volatile_bind_group {
+            Some(bind_group) => bind_group,
+            None => {
+                log::warn!("FXAA has no source image, see FXAAPass::set_source");
+                return;
+            }
+        };
 
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             color_attachments: Borrowed(&[wgpu::RenderPassColorAttachmentDescriptor {
@@ -194,12 +391,48 @@ impl FXAAPass {
         });
         rpass.push_debug_group("Prepare FXAA resources.");
         rpass.set_pipeline(&self.pipeline);
-        if let Some(ref bind_group) = self.volatile_bind_group {
-            rpass.set_bind_group(0, bind_group, &[]);
-        }
+        rpass.set_bind_group(0, volatile_bind_group, &[]);
         rpass.set_bind_group(1, &self.bind_group, &[]);
         rpass.pop_debug_group();
         rpass.insert_debug_marker("FXAA!");
         rpass.draw(0..4, 0..1);
     }
 }
+
+impl PostProcessPass for FXAAPass {
+    fn name(&self) -> &str {
+        "fxaa"
+    }
+
+    fn resize(&mut self, width: u32, height: u32, device: &wgpu::Device, queue: &wgpu::Queue) {
+        FXAAPass::resize(self, width, height, device, queue)
+    }
+
+    fn set_source(&mut self, source: &wgpu::TextureView, device: &wgpu::Device) {
+        FXAAPass::set_source(self, source, device)
+    }
+
+    fn render(
+        &mut self,
+        dest: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        FXAAPass::render(self, dest, device, queue, encoder)
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn set_shaders(
+        &mut self,
+        device: &wgpu::Device,
+        vs_words: &[u32],
+        fs_words: &[u32],
+    ) -> Result<(), reflect::InterfaceError> {
+        FXAAPass::set_shaders(self, device, vs_words, fs_words)
+    }
+
+    fn allocations(&self) -> usize {
+        FXAAPass::allocations(self)
+    }
+}
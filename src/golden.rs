@@ -0,0 +1,402 @@
+//! Golden image tests for the render passes.
+//!
+//! Each test renders offscreen through `headless::run`, reads the frame back and compares
+//! it with `tests/golden/<name>.png`. On mismatch, the rendered image and a diff image are
+//! written to `target/golden/`. Run with `ANIMA_BLESS=1` to (re)generate the references.
+//!
+//! The scenes always render on mesa's llvmpipe, through lavapipe on Vulkan or through GL,
+//! so that the references don't depend on the GPU of whoever blessed them.
+//! `ANIMA_ADAPTER` and `ANIMA_BACKEND` still override this.
+
+use crate::adapter::AdapterSelection;
+use crate::box_renderer::BoxRenderer;
+use crate::capture::{self, Image};
+use crate::error::AnimaError;
+use crate::framework;
+#[cfg(feature = "fxaa")]
+use crate::fxaa::{FXAAPass, FxaaSettings};
+use crate::headless::{self, HeadlessConfig};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Maximum per-channel difference for two pixels to be considered equal.
+/// Software and hardware rasterizers don't round exactly the same way.
+const CHANNEL_TOLERANCE: u8 = 2;
+/// Fraction of pixels allowed to exceed `CHANNEL_TOLERANCE`.
+const MAX_MISMATCH_RATIO: f64 = 0.001;
+/// Below this, the images are too far apart, even if few pixels differ.
+const MIN_PSNR: f64 = 40.0;
+
+struct Comparison {
+    mismatched: usize,
+    max_diff: u8,
+    psnr: f64,
+}
+
+fn compare(expected: &Image, actual: &Image) -> Comparison {
+    let mut mismatched = 0;
+    let mut max_diff = 0;
+    let mut squared_error = 0.0;
+
+    for (e, a) in expected.data.chunks(4).zip(actual.data.chunks(4)) {
+        let mut pixel_diff = 0;
+        for (ec, ac) in e.iter().zip(a) {
+            let diff = (*ec as i16 - *ac as i16).abs() as u8;
+            pixel_diff = pixel_diff.max(diff);
+            squared_error += (diff as f64) * (diff as f64);
+        }
+        if pixel_diff > CHANNEL_TOLERANCE {
+            mismatched += 1;
+        }
+        max_diff = max_diff.max(pixel_diff);
+    }
+
+    let mse = squared_error / expected.data.len() as f64;
+    let psnr = if mse == 0.0 {
+        std::f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    };
+
+    Comparison {
+        mismatched,
+        max_diff,
+        psnr,
+    }
+}
+
+/// Pixels within tolerance are dimmed, others are painted red.
+fn diff_image(expected: &Image, actual: &Image) -> Image {
+    let mut data = Vec::with_capacity(expected.data.len());
+    for (e, a) in expected.data.chunks(4).zip(actual.data.chunks(4)) {
+        let differs = e
+            .iter()
+            .zip(a)
+            .any(|(ec, ac)| (*ec as i16 - *ac as i16).abs() as u8 > CHANNEL_TOLERANCE);
+        if differs {
+            data.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            data.extend_from_slice(&[a[0] / 4, a[1] / 4, a[2] / 4, 255]);
+        }
+    }
+    Image {
+        width: expected.width,
+        height: expected.height,
+        data,
+    }
+}
+
+fn load_png(path: &Path) -> io::Result<Image> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let (info, mut reader) = decoder.read_info()?;
+    if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not an 8 bit RGBA image", path.display()),
+        ));
+    }
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data)?;
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        data,
+    })
+}
+
+fn manifest_path(relative: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+fn check_golden(name: &str, actual: &Image) {
+    let reference_path = manifest_path("tests/golden").join(format!("{}.png", name));
+    let output_dir = manifest_path("target/golden");
+
+    if std::env::var_os("ANIMA_BLESS").is_some() {
+        fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        actual.save_png(&reference_path).unwrap();
+        return;
+    }
+
+    fs::create_dir_all(&output_dir).unwrap();
+    let actual_path = output_dir.join(format!("{}.actual.png", name));
+
+    let expected = match load_png(&reference_path) {
+        Ok(expected) => expected,
+        Err(err) => {
+            actual.save_png(&actual_path).unwrap();
+            panic!(
+                "{}: cannot load reference {}: {}. Rendered image saved to {}, run with ANIMA_BLESS=1 to accept it.",
+                name,
+                reference_path.display(),
+                err,
+                actual_path.display(),
+            );
+        }
+    };
+
+    assert_eq!(
+        (expected.width, expected.height),
+        (actual.width, actual.height),
+        "{}: reference and rendered image sizes differ",
+        name
+    );
+
+    let comparison = compare(&expected, actual);
+    let pixel_count = (actual.width * actual.height) as f64;
+    let mismatch_ratio = comparison.mismatched as f64 / pixel_count;
+    if mismatch_ratio > MAX_MISMATCH_RATIO || comparison.psnr < MIN_PSNR {
+        let diff_path = output_dir.join(format!("{}.diff.png", name));
+        actual.save_png(&actual_path).unwrap();
+        diff_image(&expected, actual).save_png(&diff_path).unwrap();
+        panic!(
+            "{}: {} pixels differ (max channel difference {}, PSNR {:.2} dB). See {} and {}",
+            name,
+            comparison.mismatched,
+            comparison.max_diff,
+            comparison.psnr,
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+/// Renders a single frame of `E` offscreen and reads it back.
+fn render_golden<E: framework::Example>(width: u32, height: u32) -> Image {
+    let config = HeadlessConfig::new(width, height);
+    let mut image = None;
+    headless::run::<E, _>(&config, |_, texture, device, queue| {
+        image = Some(capture::read_texture(
+            device,
+            queue,
+            texture,
+            config.width,
+            config.height,
+            config.format,
+        ));
+    })
+    .unwrap_or_else(|err| panic!("Cannot render offscreen: {}", err));
+    image
+        .expect("No frame was rendered")
+        .unwrap_or_else(|err| panic!("Cannot read the frame back: {}", err))
+}
+
+struct BoxScene {
+    box_renderer: BoxRenderer,
+}
+
+/// The software rasterizer the references were rendered with.
+fn reference_adapter() -> AdapterSelection {
+    AdapterSelection {
+        backends: wgpu::BackendBit::VULKAN | wgpu::BackendBit::GL,
+        name: Some("llvmpipe".to_string()),
+        ..AdapterSelection::default()
+    }
+}
+
+impl framework::Example for BoxScene {
+    fn adapter_selection() -> AdapterSelection {
+        reference_adapter()
+    }
+
+    fn init(
+        sc_desc: &wgpu::SwapChainDescriptor,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self, AnimaError> {
+        Ok(BoxScene {
+            box_renderer: BoxRenderer::new(sc_desc.format, sc_desc.width, sc_desc.height, device, queue)?,
+        })
+    }
+
+    #[cfg(feature = "winit-runner")]
+    fn update(&mut self, _event: winit::event::WindowEvent) {}
+
+    fn resize(
+        &mut self,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        self.box_renderer.resize(sc_desc.width, sc_desc.height, device, queue);
+    }
+
+    fn render(
+        &mut self,
+        frame: &wgpu::SwapChainTexture,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        spawner: &impl futures::task::LocalSpawn,
+    ) {
+        self.render_view(&frame.view, device, queue, spawner).unwrap();
+    }
+
+    fn render_view(
+        &mut self,
+        frame: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _spawner: &impl futures::task::LocalSpawn,
+    ) -> Result<(), AnimaError> {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.box_renderer.render(frame, device, queue, &mut encoder);
+        queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fxaa")]
+struct FxaaScene {
+    box_scene: BoxScene,
+    fxaa: FXAAPass,
+    fxaa_input: wgpu::Texture,
+    fxaa_input_view: wgpu::TextureView,
+}
+
+#[cfg(feature = "fxaa")]
+impl FxaaScene {
+    fn create_input(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            format: sc_desc.format,
+            size: wgpu::Extent3d {
+                width: sc_desc.width,
+                height: sc_desc.height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            label: None,
+        })
+    }
+}
+
+#[cfg(feature = "fxaa")]
+impl framework::Example for FxaaScene {
+    fn adapter_selection() -> AdapterSelection {
+        reference_adapter()
+    }
+
+    fn init(
+        sc_desc: &wgpu::SwapChainDescriptor,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self, AnimaError> {
+        let fxaa_input = FxaaScene::create_input(device, sc_desc);
+        let fxaa_input_view = fxaa_input.create_default_view();
+        let mut fxaa = FXAAPass::new(
+            sc_desc.format,
+            sc_desc.width,
+            sc_desc.height,
+            FxaaSettings::default(),
+            device,
+            queue,
+        )?;
+        fxaa.set_source(&fxaa_input_view, device);
+        Ok(FxaaScene {
+            box_scene: BoxScene::init(sc_desc, device, queue)?,
+            fxaa,
+            fxaa_input,
+            fxaa_input_view,
+        })
+    }
+
+    #[cfg(feature = "winit-runner")]
+    fn update(&mut self, _event: winit::event::WindowEvent) {}
+
+    fn resize(
+        &mut self,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        self.fxaa_input = FxaaScene::create_input(device, sc_desc);
+        self.fxaa_input_view = self.fxaa_input.create_default_view();
+        self.box_scene.resize(sc_desc, device, queue);
+        self.fxaa.resize(sc_desc.width, sc_desc.height, device, queue);
+        self.fxaa.set_source(&self.fxaa_input_view, device);
+    }
+
+    fn render(
+        &mut self,
+        frame: &wgpu::SwapChainTexture,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        spawner: &impl futures::task::LocalSpawn,
+    ) {
+        self.render_view(&frame.view, device, queue, spawner).unwrap();
+    }
+
+    fn render_view(
+        &mut self,
+        frame: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _spawner: &impl futures::task::LocalSpawn,
+    ) -> Result<(), AnimaError> {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.box_scene.box_renderer.render(&self.fxaa_input_view, device, queue, &mut encoder);
+        self.fxaa.render(frame, device, queue, &mut encoder);
+        queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+#[test]
+fn box_renderer_256x256() {
+    check_golden("box_renderer_256x256", &render_golden::<BoxScene>(256, 256));
+}
+
+#[test]
+fn box_renderer_320x200() {
+    check_golden("box_renderer_320x200", &render_golden::<BoxScene>(320, 200));
+}
+
+#[cfg(feature = "fxaa")]
+#[test]
+fn fxaa_256x256() {
+    check_golden("fxaa_256x256", &render_golden::<FxaaScene>(256, 256));
+}
+
+#[cfg(feature = "fxaa")]
+#[test]
+fn fxaa_320x200() {
+    check_golden("fxaa_320x200", &render_golden::<FxaaScene>(320, 200));
+}
+
+fn image(width: u32, height: u32, data: &[u8]) -> Image {
+    Image {
+        width,
+        height,
+        data: data.to_vec(),
+    }
+}
+
+#[test]
+fn compare_tolerance_and_psnr() {
+    let expected = image(2, 1, &[0, 0, 0, 255, 0, 0, 0, 255]);
+    assert_eq!(compare(&expected, &expected).mismatched, 0);
+    assert_eq!(compare(&expected, &expected).psnr, std::f64::INFINITY);
+
+    // the first pixel is within tolerance, the second isn't
+    let actual = image(2, 1, &[2, 0, 0, 255, 10, 0, 0, 255]);
+    let comparison = compare(&expected, &actual);
+    assert_eq!(comparison.mismatched, 1);
+    assert_eq!(comparison.max_diff, 10);
+    // (2² + 10²) / 8 channels
+    let psnr = 10.0 * (255.0 * 255.0 / 13.0f64).log10();
+    assert!((comparison.psnr - psnr).abs() < 1e-9, "{}", comparison.psnr);
+}
+
+#[test]
+fn diff_image_marks_mismatches() {
+    let expected = image(2, 1, &[200, 100, 40, 255, 200, 100, 40, 255]);
+    let actual = image(2, 1, &[201, 100, 40, 255, 0, 0, 0, 255]);
+    let diff = diff_image(&expected, &actual);
+    assert_eq!((diff.width, diff.height), (2, 1));
+    assert_eq!(diff.data, vec![50, 25, 10, 255, 255, 0, 0, 255]);
+}
@@ -0,0 +1,538 @@
+//! A render graph: passes declare the textures they read and write by name,
+//! and the graph orders them, allocates the textures and hands out their views
+//! while recording into a single command encoder.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// The texture given to `RenderGraph::execute`, usually the swap chain frame.
+pub const BACKBUFFER: &str = "backbuffer";
+
+/// Size of a texture, relative to the backbuffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SizePolicy {
+    Full,
+    Half,
+    Fixed(u32, u32),
+}
+
+impl SizePolicy {
+    pub fn resolve(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            SizePolicy::Full => (width, height),
+            SizePolicy::Half => ((width / 2).max(1), (height / 2).max(1)),
+            SizePolicy::Fixed(width, height) => (width, height),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureDesc {
+    pub format: wgpu::TextureFormat,
+    pub size: SizePolicy,
+}
+
+impl TextureDesc {
+    pub fn new(format: wgpu::TextureFormat, size: SizePolicy) -> Self {
+        TextureDesc { format, size }
+    }
+}
+
+/// A pass and the textures it uses.
+#[derive(Clone, Debug)]
+pub struct PassDesc {
+    name: String,
+    inputs: Vec<String>,
+    outputs: Vec<(String, TextureDesc)>,
+    enabled: bool,
+}
+
+impl PassDesc {
+    pub fn new(name: &str) -> Self {
+        PassDesc {
+            name: name.to_string(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            enabled: true,
+        }
+    }
+
+    pub fn input(mut self, name: &str) -> Self {
+        self.inputs.push(name.to_string());
+        self
+    }
+
+    /// Writing to `BACKBUFFER` requires its format and the `Full` size.
+    pub fn output(mut self, name: &str, desc: TextureDesc) -> Self {
+        self.outputs.push((name.to_string(), desc));
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphError {
+    DuplicatePass(String),
+    /// A pass reads a texture no enabled pass writes.
+    MissingInput { pass: String, texture: String },
+    MultipleWriters(String),
+    /// A pass writes `BACKBUFFER` with another format or size.
+    BackbufferMismatch(String),
+    /// A pass reads `BACKBUFFER`, directly or through a disabled pass.
+    BackbufferInput(String),
+    /// A disabled pass can't forward its input, as it doesn't match its output.
+    PassthroughMismatch(String),
+    /// Passes depend on each other.
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::DuplicatePass(pass) => write!(f, "pass {} is declared twice", pass),
+            GraphError::MissingInput { pass, texture } => {
+                write!(f, "pass {} reads {}, which no enabled pass writes", pass, texture)
+            }
+            GraphError::MultipleWriters(texture) => write!(f, "{} is written by several passes", texture),
+            GraphError::BackbufferMismatch(pass) => {
+                write!(f, "pass {} writes the backbuffer with another format or size", pass)
+            }
+            GraphError::BackbufferInput(pass) => write!(f, "pass {} reads the backbuffer", pass),
+            GraphError::PassthroughMismatch(pass) => write!(
+                f,
+                "disabled pass {} can't be skipped, its input and output differ",
+                pass
+            ),
+            GraphError::Cycle(passes) => write!(f, "passes {} depend on each other", passes.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+/// The outcome of scheduling: which passes run in which order,
+/// and which physical texture backs each named one.
+#[derive(Debug)]
+struct Plan {
+    /// Indices of the passes to run, in order.
+    order: Vec<usize>,
+    /// Physical texture index for each name, None for the backbuffer.
+    bindings: HashMap<String, Option<usize>>,
+    physical: Vec<TextureDesc>,
+}
+
+/// Finds which name a texture ends up as, once disabled passes forward their input.
+fn resolve_name<'a>(aliases: &HashMap<&'a str, &'a str>, mut name: &'a str) -> &'a str {
+    while let Some(&target) = aliases.get(name) {
+        name = target;
+    }
+    name
+}
+
+fn plan(passes: &[PassDesc], backbuffer_format: wgpu::TextureFormat) -> Result<Plan, GraphError> {
+    let backbuffer_desc = TextureDesc::new(backbuffer_format, SizePolicy::Full);
+
+    let mut descs = HashMap::new();
+    for (i, pass) in passes.iter().enumerate() {
+        if passes[..i].iter().any(|other| other.name == pass.name) {
+            return Err(GraphError::DuplicatePass(pass.name.clone()));
+        }
+        if pass.inputs.iter().any(|input| input == BACKBUFFER) {
+            return Err(GraphError::BackbufferInput(pass.name.clone()));
+        }
+        for (name, desc) in &pass.outputs {
+            if name == BACKBUFFER && *desc != backbuffer_desc {
+                return Err(GraphError::BackbufferMismatch(pass.name.clone()));
+            }
+            descs.insert(name.as_str(), *desc);
+        }
+    }
+
+    // a disabled pass with one input and one output forwards the former to its readers.
+    // When it writes the backbuffer, the input gets written there directly instead.
+    let mut aliases: HashMap<&str, &str> = HashMap::new();
+    for pass in passes.iter().filter(|pass| !pass.enabled) {
+        if let ([input], [(output, output_desc)]) = (&pass.inputs[..], &pass.outputs[..]) {
+            if descs.get(input.as_str()) != Some(output_desc) {
+                return Err(GraphError::PassthroughMismatch(pass.name.clone()));
+            }
+            let input = resolve_name(&aliases, input);
+            if input == output {
+                return Err(GraphError::Cycle(vec![pass.name.clone()]));
+            }
+            if output == BACKBUFFER {
+                aliases.insert(input, BACKBUFFER);
+            } else {
+                aliases.insert(output.as_str(), input);
+            }
+        }
+    }
+
+    let enabled: Vec<usize> = (0..passes.len()).filter(|&i| passes[i].enabled).collect();
+
+    let mut writers = HashMap::new();
+    for &i in &enabled {
+        for (name, _) in &passes[i].outputs {
+            let name = resolve_name(&aliases, name);
+            if writers.insert(name, i).is_some() {
+                return Err(GraphError::MultipleWriters(name.to_string()));
+            }
+        }
+    }
+
+    // dependencies[i]: passes which have to run before pass i
+    let mut dependencies: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &i in &enabled {
+        let mut before = Vec::new();
+        for input in &passes[i].inputs {
+            let name = resolve_name(&aliases, input);
+            if name == BACKBUFFER {
+                return Err(GraphError::BackbufferInput(passes[i].name.clone()));
+            }
+            match writers.get(name) {
+                Some(&writer) => before.push(writer),
+                None => {
+                    return Err(GraphError::MissingInput {
+                        pass: passes[i].name.clone(),
+                        texture: input.clone(),
+                    })
+                }
+            }
+        }
+        dependencies.insert(i, before);
+    }
+
+    // topological sort, keeping the declaration order among independent passes
+    let mut order = Vec::with_capacity(enabled.len());
+    while order.len() < enabled.len() {
+        let next = enabled.iter().copied().find(|i| {
+            !order.contains(i) && dependencies[i].iter().all(|dependency| order.contains(dependency))
+        });
+        match next {
+            Some(i) => order.push(i),
+            None => {
+                let stuck = enabled
+                    .iter()
+                    .filter(|i| !order.contains(i))
+                    .map(|&i| passes[i].name.clone())
+                    .collect();
+                return Err(GraphError::Cycle(stuck));
+            }
+        }
+    }
+
+    // lifetime of each texture, as the range of steps from its write to its last read
+    let mut lifetimes: Vec<(&str, usize, usize)> = Vec::new();
+    for (step, &i) in order.iter().enumerate() {
+        for (name, _) in &passes[i].outputs {
+            let name = resolve_name(&aliases, name);
+            if name != BACKBUFFER {
+                lifetimes.push((name, step, step));
+            }
+        }
+        for input in &passes[i].inputs {
+            let name = resolve_name(&aliases, input);
+            if let Some(lifetime) = lifetimes.iter_mut().find(|lifetime| lifetime.0 == name) {
+                lifetime.2 = step;
+            }
+        }
+    }
+
+    // textures which are never alive at the same time share memory
+    let mut bindings = HashMap::new();
+    bindings.insert(BACKBUFFER.to_string(), None);
+    let mut physical: Vec<TextureDesc> = Vec::new();
+    let mut last_uses: Vec<usize> = Vec::new();
+    for &(name, first, last) in &lifetimes {
+        let desc = descs[name];
+        let reusable = (0..physical.len()).find(|&p| physical[p] == desc && last_uses[p] < first);
+        let index = match reusable {
+            Some(p) => {
+                last_uses[p] = last;
+                p
+            }
+            None => {
+                physical.push(desc);
+                last_uses.push(last);
+                physical.len() - 1
+            }
+        };
+        bindings.insert(name.to_string(), Some(index));
+    }
+    // forwarded names point to the same texture as their target
+    for &name in aliases.keys() {
+        let target = resolve_name(&aliases, name);
+        if let Some(&binding) = bindings.get(target) {
+            bindings.insert(name.to_string(), binding);
+        }
+    }
+
+    Ok(Plan {
+        order,
+        bindings,
+        physical,
+    })
+}
+
+struct Target {
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+/// The textures a pass declared, while it records.
+pub struct PassResources<'a> {
+    views: Vec<(&'a str, &'a wgpu::TextureView)>,
+    generation: u64,
+}
+
+impl<'a> PassResources<'a> {
+    /// Changes whenever the graph's textures are recreated, so passes know when
+    /// to rebuild what they keep about the views.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// None if the pass didn't declare `name` as an input or output.
+    pub fn view(&self, name: &str) -> Option<&'a wgpu::TextureView> {
+        self.views
+            .iter()
+            .find(|(view_name, _)| *view_name == name)
+            .map(|(_, view)| *view)
+    }
+}
+
+/// Passes are recorded through the callback given to `execute`, so they can
+/// borrow whatever renderer they need.
+///
+/// Textures are recreated when passes are added, enabled or disabled, and on resize,
+/// which bumps `PassResources::generation`.
+pub struct RenderGraph {
+    passes: Vec<PassDesc>,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    plan: Option<Plan>,
+    targets: Vec<Target>,
+    generation: u64,
+}
+
+impl RenderGraph {
+    pub fn new(format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        RenderGraph {
+            passes: Vec::new(),
+            format,
+            width,
+            height,
+            plan: None,
+            targets: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn add_pass(&mut self, pass: PassDesc) {
+        self.passes.push(pass);
+        self.plan = None;
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.passes.iter().any(|pass| pass.name == name && pass.enabled)
+    }
+
+    /// A disabled pass with a single input and output forwards the input in place
+    /// of its output. Returns false if there is no pass with this name.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.passes.iter_mut().find(|pass| pass.name == name) {
+            Some(pass) => {
+                if pass.enabled != enabled {
+                    pass.enabled = enabled;
+                    self.plan = None;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn create_targets(&mut self, physical: &[TextureDesc], device: &wgpu::Device) {
+        let (width, height) = (self.width, self.height);
+        self.targets = physical
+            .iter()
+            .map(|desc| {
+                let (width, height) = desc.size.resolve(width, height);
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    format: desc.format,
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+                    label: Some("Render graph texture"),
+                });
+                let view = texture.create_default_view();
+                Target {
+                    _texture: texture,
+                    view,
+                }
+            })
+            .collect();
+        self.generation += 1;
+    }
+
+    /// Orders the passes and allocates their textures. Done by `execute` when needed,
+    /// this is useful to check the graph up front.
+    pub fn compile(&mut self, device: &wgpu::Device) -> Result<(), GraphError> {
+        let plan = self.take_plan(device)?;
+        self.plan = Some(plan);
+        Ok(())
+    }
+
+    /// The current plan, or a new one with its textures if the passes changed.
+    fn take_plan(&mut self, device: &wgpu::Device) -> Result<Plan, GraphError> {
+        match self.plan.take() {
+            Some(plan) => Ok(plan),
+            None => {
+                let plan = plan(&self.passes, self.format)?;
+                self.create_targets(&plan.physical, device);
+                Ok(plan)
+            }
+        }
+    }
+
+    /// A zero size, as given for minimized windows, is ignored.
+    pub fn resize(&mut self, width: u32, height: u32, device: &wgpu::Device) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        if let Some(plan) = self.plan.take() {
+            self.create_targets(&plan.physical, device);
+            self.plan = Some(plan);
+        }
+    }
+
+    /// Calls `record` for each enabled pass, in dependency order, with the views of its textures.
+    pub fn execute<F>(
+        &mut self,
+        backbuffer: &wgpu::TextureView,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        mut record: F,
+    ) -> Result<(), GraphError>
+    where
+        F: FnMut(&str, &PassResources, &mut wgpu::CommandEncoder),
+    {
+        let plan = self.take_plan(device)?;
+
+        for &i in &plan.order {
+            let pass = &self.passes[i];
+            let names = pass.inputs.iter().chain(pass.outputs.iter().map(|(name, _)| name));
+            let views = names
+                .map(|name| {
+                    let view = match plan.bindings[name] {
+                        Some(index) => &self.targets[index].view,
+                        None => backbuffer,
+                    };
+                    (name.as_str(), view)
+                })
+                .collect();
+            let resources = PassResources {
+                views,
+                generation: self.generation,
+            };
+            record(&pass.name, &resources, encoder);
+        }
+        self.plan = Some(plan);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+
+    fn full() -> TextureDesc {
+        TextureDesc::new(FORMAT, SizePolicy::Full)
+    }
+
+    #[test]
+    fn ordering_and_aliasing() {
+        let passes = [
+            PassDesc::new("post").input("blurred").output(BACKBUFFER, full()),
+            PassDesc::new("blur").input("scene").output("blurred", full()),
+            PassDesc::new("scene").output("scene", full()),
+        ];
+        let plan = plan(&passes, FORMAT).unwrap();
+        assert_eq!(plan.order, vec![2, 1, 0]);
+        // "scene" is still read while "blurred" is written
+        assert_eq!(plan.physical.len(), 2);
+        assert_eq!(plan.bindings[BACKBUFFER], None);
+    }
+
+    #[test]
+    fn reuse_after_last_read() {
+        let passes = [
+            PassDesc::new("a").output("a", full()),
+            PassDesc::new("b").input("a").output("b", full()),
+            PassDesc::new("c").input("b").output("c", full()),
+            PassDesc::new("d").input("c").output(BACKBUFFER, full()),
+        ];
+        let plan = plan(&passes, FORMAT).unwrap();
+        assert_eq!(plan.physical.len(), 2);
+        assert_eq!(plan.bindings["a"], plan.bindings["c"]);
+    }
+
+    #[test]
+    fn disabled_pass_forwards_its_input() {
+        let mut passes = vec![
+            PassDesc::new("scene").output("scene", full()),
+            PassDesc::new("fxaa").input("scene").output(BACKBUFFER, full()),
+        ];
+        passes[1].enabled = false;
+        let plan = plan(&passes, FORMAT).unwrap();
+        assert_eq!(plan.order, vec![0]);
+        assert_eq!(plan.bindings["scene"], None);
+    }
+
+    #[test]
+    fn errors() {
+        let cycle = [
+            PassDesc::new("a").input("b").output("a", full()),
+            PassDesc::new("b").input("a").output("b", full()),
+        ];
+        assert!(matches!(plan(&cycle, FORMAT), Err(GraphError::Cycle(_))));
+
+        let missing = [PassDesc::new("a").input("b").output(BACKBUFFER, full())];
+        assert!(matches!(plan(&missing, FORMAT), Err(GraphError::MissingInput { .. })));
+
+        let half = TextureDesc::new(FORMAT, SizePolicy::Half);
+        let backbuffer = [PassDesc::new("a").output(BACKBUFFER, half)];
+        assert!(matches!(plan(&backbuffer, FORMAT), Err(GraphError::BackbufferMismatch(_))));
+    }
+
+    #[test]
+    fn backbuffer_input() {
+        let direct = [
+            PassDesc::new("a").output(BACKBUFFER, full()),
+            PassDesc::new("b").input(BACKBUFFER).output("b", full()),
+        ];
+        assert_eq!(plan(&direct, FORMAT).unwrap_err(), GraphError::BackbufferInput("b".to_string()));
+
+        // "scene" ends up in the backbuffer once "post" is disabled
+        let mut forwarded = vec![
+            PassDesc::new("scene").output("scene", full()),
+            PassDesc::new("post").input("scene").output(BACKBUFFER, full()),
+            PassDesc::new("overlay").input("scene").output("overlay", full()),
+        ];
+        forwarded[1].enabled = false;
+        assert_eq!(
+            plan(&forwarded, FORMAT).unwrap_err(),
+            GraphError::BackbufferInput("overlay".to_string())
+        );
+    }
+}
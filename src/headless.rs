@@ -0,0 +1,114 @@
+use crate::adapter;
+use crate::capture;
+use crate::clock::{FakeClock, Timestep};
+use crate::error::AnimaError;
+use crate::framework::{self, Example};
+use std::time::Duration;
+
+/// Describes the offscreen target frames get rendered to.
+pub struct HeadlessConfig {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub frame_count: u32,
+    /// Simulated time between two frames, to make runs reproducible.
+    pub frame_time: Duration,
+}
+
+impl HeadlessConfig {
+    pub fn new(width: u32, height: u32) -> Self {
+        HeadlessConfig {
+            width,
+            height,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            frame_count: 1,
+            frame_time: Duration::from_micros(16_667),
+        }
+    }
+}
+
+pub struct Headless {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub adapter_info: wgpu::AdapterInfo,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+impl Headless {
+    /// Creates the example and tells it about the adapter, as the windowed runner does.
+    pub fn init<E: Example>(&self, sc_desc: &wgpu::SwapChainDescriptor) -> Result<E, AnimaError> {
+        let mut example = E::init(sc_desc, &self.device, &self.queue)?;
+        example.adapter_info(&self.adapter_info);
+        Ok(example)
+    }
+}
+
+/// Same as the windowed setup, without any window nor surface.
+/// The adapter selection is only overridden by the environment, not the command line.
+pub async fn setup<E: Example>() -> Result<Headless, AnimaError> {
+    let selection = E::adapter_selection().with_env_overrides();
+    let instance = wgpu::Instance::new(selection.backends);
+
+    let adapter = adapter::request_adapter(&instance, &selection, None).await?;
+    let (device, queue) = framework::request_device::<E>(&adapter).await?;
+    adapter::report(&adapter, &device);
+
+    Ok(Headless {
+        instance,
+        adapter_info: adapter.get_info(),
+        adapter,
+        device,
+        queue,
+    })
+}
+
+/// The descriptor handed to `Example::init` and `Example::resize`.
+/// There is no swap chain, but examples size their resources after it.
+pub fn sc_desc(config: &HeadlessConfig) -> wgpu::SwapChainDescriptor {
+    wgpu::SwapChainDescriptor {
+        usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        format: config.format,
+        width: config.width,
+        height: config.height,
+        present_mode: wgpu::PresentMode::Fifo,
+    }
+}
+
+/// Creates a texture examples can render to in place of a swap chain frame.
+pub fn create_target(device: &wgpu::Device, config: &HeadlessConfig) -> wgpu::Texture {
+    capture::create_target(device, config.width, config.height, config.format)
+}
+
+/// Renders `config.frame_count` frames of `E` offscreen, through `Example::render_view`.
+/// After each frame is submitted, `on_frame` is called with the frame index and
+/// the texture it was rendered to. The texture is reused between frames.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run<E, F>(config: &HeadlessConfig, mut on_frame: F) -> Result<(), AnimaError>
+where
+    E: Example,
+    F: FnMut(u32, &wgpu::Texture, &wgpu::Device, &wgpu::Queue),
+{
+    let headless = futures::executor::block_on(setup::<E>())?;
+    let Headless { device, queue, .. } = &headless;
+
+    let mut pool = futures::executor::LocalPool::new();
+    let spawner = pool.spawner();
+
+    log::info!("Initializing the example...");
+    let mut example: E = headless.init(&sc_desc(config))?;
+
+    let target = create_target(device, config);
+    let target_view = target.create_default_view();
+
+    let mut timestep = Timestep::new(Box::new(FakeClock::new(config.frame_time)), E::fixed_timestep());
+
+    log::info!("Rendering {} frames...", config.frame_count);
+    for frame_index in 0..config.frame_count {
+        timestep.advance(&mut example);
+        example.render_view(&target_view, device, queue, &spawner)?;
+        pool.run_until_stalled();
+        on_frame(frame_index, &target, device, queue);
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! Box, mesh and voxel rendering on top of wgpu, with post-processing and a render graph.
+//!
+//! Optional features:
+//! - `fxaa`: the FXAA post-processing pass.
+//! - `winit-runner`: `framework::run`, which opens a window and drives an `Example`,
+//!   and the window events examples and the camera handle.
+//! - `headless`: offscreen rendering of an `Example`, for tests and captures,
+//!   and saving frames with F12 in the windowed runner.
+//! - `image`: loading textures from PNG and JPEG files.
+//! - `gltf`: loading glTF scenes.
+//! - `hot-reload`: runtime shader compilation, to edit shaders while running.
+
+pub mod adapter;
+pub mod animation;
+pub mod box_renderer;
+pub mod builders;
+pub mod camera;
+#[cfg(feature = "headless")]
+pub mod capture;
+pub mod clock;
+pub mod error;
+pub mod framework;
+#[cfg(feature = "fxaa")]
+pub mod fxaa;
+pub mod graph;
+#[cfg(feature = "headless")]
+pub mod headless;
+pub mod lighting;
+pub mod mesh;
+pub mod msaa;
+pub mod postprocess;
+pub mod reflect;
+pub mod scene;
+#[cfg(feature = "hot-reload")]
+pub mod shaders;
+pub mod texture;
+pub mod vox;
+pub mod voxel;
+
+#[cfg(all(test, feature = "headless"))]
+mod golden;
+
+pub use box_renderer::BoxRenderer;
+pub use error::AnimaError;
+pub use framework::Example;
+#[cfg(feature = "fxaa")]
+pub use fxaa::FXAAPass;
@@ -0,0 +1,58 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Point3, Vector3};
+
+/// A directional light with an ambient term, shaded with Blinn-Phong.
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    /// Direction the light comes from, in world space.
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub ambient: [f32; 3],
+    /// Multiplied with the instance color and texture.
+    pub base_color: [f32; 4],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Light {
+            direction: Vector3::new(0.4, -0.6, 1.0),
+            color: [1.0, 1.0, 1.0],
+            ambient: [0.2, 0.2, 0.2],
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            specular: [0.3, 0.3, 0.3],
+            shininess: 32.0,
+        }
+    }
+}
+
+/// Matches the std140 layout of `Lighting` in shader.frag.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LightUniforms {
+    direction: [f32; 4],
+    color: [f32; 4],
+    ambient: [f32; 4],
+    base_color: [f32; 4],
+    /// rgb: specular color, a: shininess
+    specular: [f32; 4],
+    eye_position: [f32; 4],
+}
+
+unsafe impl Pod for LightUniforms {}
+unsafe impl Zeroable for LightUniforms {}
+
+impl LightUniforms {
+    pub fn new(light: &Light, eye_position: Point3<f32>) -> Self {
+        let direction = light.direction.normalize();
+        LightUniforms {
+            direction: [direction.x, direction.y, direction.z, 0.0],
+            color: [light.color[0], light.color[1], light.color[2], 1.0],
+            ambient: [light.ambient[0], light.ambient[1], light.ambient[2], 1.0],
+            base_color: light.base_color,
+            specular: [light.specular[0], light.specular[1], light.specular[2], light.shininess],
+            eye_position: [eye_position.x, eye_position.y, eye_position.z, 1.0],
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Triangle meshes in the box pipeline's vertex format, and their loaders.
+
+mod obj;
+mod stl;
+
+use crate::box_renderer::Vertex;
+use cgmath::{InnerSpace, Vector3};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io(io::Error),
+    /// `line` is 0 for binary files.
+    Parse { line: usize, message: String },
+    UnknownFormat(PathBuf),
+}
+
+impl MeshError {
+    fn parse(line: usize, message: impl Into<String>) -> Self {
+        MeshError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::Io(err) => write!(f, "{}", err),
+            MeshError::Parse { line: 0, message } => write!(f, "{}", message),
+            MeshError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            MeshError::UnknownFormat(path) => write!(f, "unknown mesh format: {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+impl From<io::Error> for MeshError {
+    fn from(err: io::Error) -> Self {
+        MeshError::Io(err)
+    }
+}
+
+/// An indexed triangle list. Indices are stored as `u32`, and uploaded as `u16`
+/// when there are few enough vertices.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        Mesh { vertices, indices }
+    }
+
+    /// Loads an OBJ or STL file, picked by extension.
+    /// OBJ materials are looked up next to the file.
+    pub fn load(path: &Path) -> Result<Self, MeshError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("obj") => obj::load(path),
+            Some("stl") => stl::parse(&std::fs::read(path)?),
+            _ => Err(MeshError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    pub fn index_format(&self) -> wgpu::IndexFormat {
+        if self.vertices.len() <= u16::MAX as usize + 1 {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        }
+    }
+
+    /// Sets smooth normals, averaging the normals of the triangles around each vertex,
+    /// weighted by their area.
+    pub fn compute_normals(&mut self) {
+        let vertices = &self.vertices;
+        let position = |i: u32| {
+            let pos = vertices[i as usize].pos;
+            Vector3::new(pos[0], pos[1], pos[2])
+        };
+
+        let mut normals = vec![Vector3::new(0f32, 0., 0.); vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let (a, b, c) = (position(triangle[0]), position(triangle[1]), position(triangle[2]));
+            // not normalized, so bigger triangles weigh more
+            let normal = (b - a).cross(c - a);
+            for &i in triangle {
+                normals[i as usize] += normal;
+            }
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            if normal.magnitude2() > 0. {
+                vertex.normal = normal.normalize().into();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_normals() {
+        // two triangles folded along the x axis
+        let vertices = vec![
+            Vertex::new([0., 0., 0.], [0., 0.], [0., 0., 0.]),
+            Vertex::new([1., 0., 0.], [0., 0.], [0., 0., 0.]),
+            Vertex::new([0., 1., 0.], [0., 0.], [0., 0., 0.]),
+            Vertex::new([0., -1., 1.], [0., 0.], [0., 0., 0.]),
+        ];
+        let mut mesh = Mesh::new(vertices, vec![0, 1, 2, 0, 3, 1]);
+        mesh.compute_normals();
+        assert_eq!(mesh.vertices[2].normal, [0., 0., 1.]);
+        let shared = mesh.vertices[0].normal;
+        assert!(shared[1] > 0. && shared[2] > 0.);
+    }
+}
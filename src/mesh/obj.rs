@@ -0,0 +1,224 @@
+//! Wavefront OBJ, with diffuse colors from MTL material libraries.
+
+use super::{Mesh, MeshError};
+use crate::box_renderer::Vertex;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// A face corner: position, texture coordinate and normal indices, and the color bits.
+type VertexKey = (usize, Option<usize>, Option<usize>, [u32; 4]);
+
+pub(super) fn load(path: &Path) -> Result<Mesh, MeshError> {
+    let source = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse(&source, |name| std::fs::read_to_string(dir.join(name)))
+}
+
+/// Diffuse color of each material, from `Kd` and `d`.
+fn parse_mtl(source: &str) -> Result<HashMap<String, [f32; 4]>, MeshError> {
+    let mut materials = HashMap::new();
+    let mut current = None;
+    for (i, line) in source.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("newmtl") => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                materials.insert(name.clone(), [1.0; 4]);
+                current = Some(name);
+            }
+            Some(keyword @ "Kd") | Some(keyword @ "d") => {
+                let color = current
+                    .as_ref()
+                    .and_then(|name| materials.get_mut(name))
+                    .ok_or_else(|| MeshError::parse(i + 1, format!("{} outside of a material", keyword)))?;
+                let values = parse_floats(words, i + 1)?;
+                match (keyword, &values[..]) {
+                    ("Kd", [r, g, b]) => color[..3].copy_from_slice(&[*r, *g, *b]),
+                    ("d", [alpha]) => color[3] = *alpha,
+                    _ => return Err(MeshError::parse(i + 1, format!("bad {} value", keyword))),
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+fn parse_floats<'a>(words: impl Iterator<Item = &'a str>, line: usize) -> Result<Vec<f32>, MeshError> {
+    words
+        .map(|word| {
+            word.parse()
+                .map_err(|_| MeshError::parse(line, format!("invalid number: {}", word)))
+        })
+        .collect()
+}
+
+/// Turns a 1-based, or negative relative, OBJ index into a 0-based one.
+fn resolve_index(word: &str, count: usize, line: usize) -> Result<usize, MeshError> {
+    let invalid = || MeshError::parse(line, format!("invalid index: {}", word));
+    let index: i64 = word.parse().map_err(|_| invalid())?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(invalid());
+    }
+    Ok(resolved as usize)
+}
+
+/// `read_mtl` loads the material libraries the source refers to.
+/// Missing ones only cause a warning, and their materials to be white.
+fn parse(source: &str, mut read_mtl: impl FnMut(&str) -> io::Result<String>) -> Result<Mesh, MeshError> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut materials = HashMap::new();
+    let mut color = [1.0f32; 4];
+
+    let mut mesh = Mesh::default();
+    let mut vertex_ids: HashMap<VertexKey, u32> = HashMap::new();
+    let mut has_normal = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let values = parse_floats(words.take(3), line_number)?;
+                match values[..] {
+                    [x, y, z] => positions.push([x, y, z]),
+                    _ => return Err(MeshError::parse(line_number, "expected 3 coordinates")),
+                }
+            }
+            Some("vt") => {
+                let values = parse_floats(words.take(2), line_number)?;
+                match values[..] {
+                    // OBJ has v going up, textures are stored top to bottom
+                    [u, v] => tex_coords.push([u, 1. - v]),
+                    [u] => tex_coords.push([u, 1.]),
+                    _ => return Err(MeshError::parse(line_number, "expected texture coordinates")),
+                }
+            }
+            Some("vn") => {
+                let values = parse_floats(words, line_number)?;
+                match values[..] {
+                    [x, y, z] => normals.push([x, y, z]),
+                    _ => return Err(MeshError::parse(line_number, "expected 3 coordinates")),
+                }
+            }
+            Some("f") => {
+                let mut face = Vec::new();
+                for word in words {
+                    let mut parts = word.split('/');
+                    let position = resolve_index(parts.next().unwrap(), positions.len(), line_number)?;
+                    let tex_coord = match parts.next() {
+                        Some("") | None => None,
+                        Some(part) => Some(resolve_index(part, tex_coords.len(), line_number)?),
+                    };
+                    let normal = match parts.next() {
+                        Some("") | None => None,
+                        Some(part) => Some(resolve_index(part, normals.len(), line_number)?),
+                    };
+
+                    let key = (position, tex_coord, normal, [
+                        color[0].to_bits(),
+                        color[1].to_bits(),
+                        color[2].to_bits(),
+                        color[3].to_bits(),
+                    ]);
+                    let id = *vertex_ids.entry(key).or_insert_with(|| {
+                        let mut vertex = Vertex::new(
+                            positions[position],
+                            tex_coord.map_or([0., 0.], |i| tex_coords[i]),
+                            normal.map_or([0., 0., 0.], |i| normals[i]),
+                        );
+                        vertex.color = color;
+                        mesh.vertices.push(vertex);
+                        has_normal.push(normal.is_some());
+                        mesh.vertices.len() as u32 - 1
+                    });
+                    face.push(id);
+                }
+                if face.len() < 3 {
+                    return Err(MeshError::parse(line_number, "faces need at least 3 vertices"));
+                }
+                // polygons are assumed convex, and split in a fan
+                for j in 1..face.len() - 1 {
+                    mesh.indices.extend_from_slice(&[face[0], face[j], face[j + 1]]);
+                }
+            }
+            Some("mtllib") => {
+                for name in words {
+                    match read_mtl(name) {
+                        Ok(mtl) => materials.extend(parse_mtl(&mtl)?),
+                        Err(err) => log::warn!("Cannot read material library {}: {}", name, err),
+                    }
+                }
+            }
+            Some("usemtl") => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                color = match materials.get(&name) {
+                    Some(&color) => color,
+                    None => {
+                        log::warn!("Unknown material {}, line {}", name, line_number);
+                        [1.0; 4]
+                    }
+                };
+            }
+            _ => {}
+        }
+    }
+
+    if has_normal.iter().any(|&has_normal| !has_normal) {
+        let given: Vec<[f32; 3]> = mesh.vertices.iter().map(|vertex| vertex.normal).collect();
+        mesh.compute_normals();
+        for ((vertex, normal), has_normal) in mesh.vertices.iter_mut().zip(given).zip(has_normal) {
+            if has_normal {
+                vertex.normal = normal;
+            }
+        }
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "
+mtllib quad.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+usemtl red
+f 1 2 3 -1
+";
+
+    const MTL: &str = "
+newmtl red
+Kd 1 0 0
+d 0.5
+";
+
+    #[test]
+    fn quad_with_material() {
+        let mesh = parse(QUAD, |name| {
+            assert_eq!(name, "quad.mtl");
+            Ok(MTL.to_string())
+        })
+        .unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.vertices[0].color, [1., 0., 0., 0.5]);
+        // computed, as the file has none
+        assert_eq!(mesh.vertices[0].normal, [0., 0., 1.]);
+    }
+
+    #[test]
+    fn invalid_index() {
+        match parse("v 0 0 0\nf 1 2 3", |_| Ok(String::new())) {
+            Err(MeshError::Parse { line: 2, .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
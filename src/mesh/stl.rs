@@ -0,0 +1,128 @@
+//! Binary and ASCII STL. Triangles don't share vertices, so the mesh is flat shaded.
+
+use super::{Mesh, MeshError};
+use crate::box_renderer::Vertex;
+use std::convert::TryInto;
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+/// Binary files may start with "solid" too, so they are told apart by their size.
+pub(super) fn parse(data: &[u8]) -> Result<Mesh, MeshError> {
+    if data.len() >= HEADER_SIZE + 4 {
+        let count = u32::from_le_bytes(data[HEADER_SIZE..HEADER_SIZE + 4].try_into().unwrap()) as usize;
+        if data.len() == HEADER_SIZE + 4 + count * TRIANGLE_SIZE {
+            return Ok(parse_binary(&data[HEADER_SIZE + 4..]));
+        }
+    }
+    if data.starts_with(b"solid") {
+        let source = std::str::from_utf8(data).map_err(|_| MeshError::parse(0, "ASCII STL is not valid UTF-8"))?;
+        return parse_ascii(source);
+    }
+    Err(MeshError::parse(0, "neither a binary nor an ASCII STL file"))
+}
+
+fn read_vector(data: &[u8]) -> [f32; 3] {
+    let float = |i: usize| f32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+    [float(0), float(1), float(2)]
+}
+
+fn parse_binary(triangles: &[u8]) -> Mesh {
+    let mut mesh = Mesh::default();
+    for triangle in triangles.chunks_exact(TRIANGLE_SIZE) {
+        let normal = read_vector(&triangle[0..12]);
+        let corners = [
+            read_vector(&triangle[12..24]),
+            read_vector(&triangle[24..36]),
+            read_vector(&triangle[36..48]),
+        ];
+        // the 2 last bytes are an "attribute byte count", unused
+        push_triangle(&mut mesh, normal, corners);
+    }
+    finish(mesh)
+}
+
+fn parse_ascii(source: &str) -> Result<Mesh, MeshError> {
+    let mut mesh = Mesh::default();
+    let mut normal = [0.; 3];
+    let mut corners = Vec::with_capacity(3);
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut words = line.split_whitespace();
+        let vector = |words: std::str::SplitWhitespace| -> Result<[f32; 3], MeshError> {
+            let values = words
+                .map(|word| word.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| MeshError::parse(line_number, "invalid number"))?;
+            match values[..] {
+                [x, y, z] => Ok([x, y, z]),
+                _ => Err(MeshError::parse(line_number, "expected 3 coordinates")),
+            }
+        };
+
+        match words.next() {
+            Some("facet") => {
+                if words.next() != Some("normal") {
+                    return Err(MeshError::parse(line_number, "expected a facet normal"));
+                }
+                normal = vector(words)?;
+                corners.clear();
+            }
+            Some("vertex") => corners.push(vector(words)?),
+            Some("endfacet") => match corners[..] {
+                [a, b, c] => push_triangle(&mut mesh, normal, [a, b, c]),
+                _ => return Err(MeshError::parse(line_number, "facets need 3 vertices")),
+            },
+            _ => {}
+        }
+    }
+    Ok(finish(mesh))
+}
+
+fn push_triangle(mesh: &mut Mesh, normal: [f32; 3], corners: [[f32; 3]; 3]) {
+    for &corner in &corners {
+        mesh.indices.push(mesh.vertices.len() as u32);
+        mesh.vertices.push(Vertex::new(corner, [0., 0.], normal));
+    }
+}
+
+/// Many exporters leave the facet normals zeroed.
+fn finish(mut mesh: Mesh) -> Mesh {
+    if mesh.vertices.iter().any(|vertex| vertex.normal == [0.; 3]) {
+        mesh.compute_normals();
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_and_binary() {
+        let ascii = "solid test
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid test
+";
+        let mesh = parse(ascii.as_bytes()).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.vertices[1].normal, [0., 0., 1.]);
+
+        let mut binary = vec![0u8; HEADER_SIZE];
+        binary.extend_from_slice(&1u32.to_le_bytes());
+        for value in &[0f32, 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0.] {
+            binary.extend_from_slice(&value.to_le_bytes());
+        }
+        binary.extend_from_slice(&[0, 0]);
+        let mesh = parse(&binary).unwrap();
+        assert_eq!(mesh.vertices[2].pos, [0., 1., 0., 1.]);
+        assert_eq!(mesh.vertices[0].normal, [0., 0., 1.]);
+    }
+}
@@ -0,0 +1,129 @@
+//! Multisample anti-aliasing settings shared by the renderers.
+
+use std::fmt;
+
+/// Sample counts the renderers accept, in increasing order.
+pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleCountError {
+    /// Not one of `SAMPLE_COUNTS`.
+    Invalid(u32),
+    /// Valid, but not supported on the adapter's backend.
+    Unsupported { sample_count: u32, backend: wgpu::Backend },
+    /// Valid, but the target format can't be multisampled.
+    UnsupportedFormat { sample_count: u32, format: wgpu::TextureFormat },
+}
+
+impl fmt::Display for SampleCountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SampleCountError::Invalid(sample_count) => {
+                write!(f, "invalid sample count {}, expected one of {:?}", sample_count, SAMPLE_COUNTS)
+            }
+            SampleCountError::Unsupported {
+                sample_count,
+                backend,
+            } => write!(f, "{}x MSAA is not supported on {:?}", sample_count, backend),
+            SampleCountError::UnsupportedFormat {
+                sample_count,
+                format,
+            } => write!(f, "{}x MSAA is not supported for {:?} targets", sample_count, format),
+        }
+    }
+}
+
+impl std::error::Error for SampleCountError {}
+
+/// The sample counts usable with `adapter` for `format` targets.
+///
+/// wgpu can't be asked which counts a format supports yet, so this sticks to
+/// the 1 and 4 that WebGPU guarantees.
+pub fn supported_sample_counts(_adapter: &wgpu::AdapterInfo, format: wgpu::TextureFormat) -> &'static [u32] {
+    if is_multisample_format(format) {
+        &[1, 4]
+    } else {
+        &[1]
+    }
+}
+
+/// Whether `format` can be multisampled at all. WebGPU leaves it out
+/// for the 32 bit per channel formats.
+pub fn is_multisample_format(format: wgpu::TextureFormat) -> bool {
+    use wgpu::TextureFormat::*;
+
+    !matches!(
+        format,
+        R32Uint | R32Sint | R32Float | Rg32Uint | Rg32Sint | Rg32Float | Rgba32Uint | Rgba32Sint | Rgba32Float
+    )
+}
+
+pub fn validate_sample_count(
+    sample_count: u32,
+    adapter: &wgpu::AdapterInfo,
+    format: wgpu::TextureFormat,
+) -> Result<u32, SampleCountError> {
+    if !SAMPLE_COUNTS.contains(&sample_count) {
+        return Err(SampleCountError::Invalid(sample_count));
+    }
+    if sample_count > 1 && !is_multisample_format(format) {
+        return Err(SampleCountError::UnsupportedFormat { sample_count, format });
+    }
+    if !supported_sample_counts(adapter, format).contains(&sample_count) {
+        return Err(SampleCountError::Unsupported {
+            sample_count,
+            backend: adapter.backend,
+        });
+    }
+    Ok(sample_count)
+}
+
+/// The next supported sample count after `sample_count`, wrapping around to 1.
+pub fn next_sample_count(sample_count: u32, adapter: &wgpu::AdapterInfo, format: wgpu::TextureFormat) -> u32 {
+    let counts = supported_sample_counts(adapter, format);
+    counts
+        .iter()
+        .copied()
+        .find(|&count| count > sample_count)
+        .unwrap_or(counts[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter(backend: wgpu::Backend) -> wgpu::AdapterInfo {
+        wgpu::AdapterInfo {
+            name: String::new(),
+            vendor: 0,
+            device: 0,
+            device_type: wgpu::DeviceType::Other,
+            backend,
+        }
+    }
+
+    #[test]
+    fn sample_count_validation() {
+        let vulkan = adapter(wgpu::Backend::Vulkan);
+        let format = wgpu::TextureFormat::Bgra8UnormSrgb;
+        assert_eq!(validate_sample_count(4, &vulkan, format), Ok(4));
+        assert_eq!(validate_sample_count(3, &vulkan, format), Err(SampleCountError::Invalid(3)));
+        assert!(validate_sample_count(8, &vulkan, format).is_err());
+        assert!(validate_sample_count(2, &adapter(wgpu::Backend::Metal), format).is_err());
+        assert_eq!(next_sample_count(1, &vulkan, format), 4);
+        assert_eq!(next_sample_count(2, &vulkan, format), 4);
+        assert_eq!(next_sample_count(4, &vulkan, format), 1);
+    }
+
+    #[test]
+    fn float_targets_are_not_multisampled() {
+        let vulkan = adapter(wgpu::Backend::Vulkan);
+        let format = wgpu::TextureFormat::Rgba32Float;
+        assert_eq!(
+            validate_sample_count(4, &vulkan, format),
+            Err(SampleCountError::UnsupportedFormat { sample_count: 4, format })
+        );
+        assert_eq!(validate_sample_count(1, &vulkan, format), Ok(1));
+        assert_eq!(next_sample_count(1, &vulkan, format), 1);
+    }
+}
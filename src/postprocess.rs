@@ -0,0 +1,359 @@
+//! Fullscreen passes run one after the other on a rendered image.
+
+#[cfg(feature = "hot-reload")]
+use crate::reflect;
+
+/// A fullscreen pass reading the view given to `set_source` and writing every pixel of `dest`.
+pub trait PostProcessPass {
+    /// Identifies the pass in a `PostProcessChain`.
+    fn name(&self) -> &str;
+    fn resize(&mut self, width: u32, height: u32, device: &wgpu::Device, queue: &wgpu::Queue);
+    /// Called before `render` whenever the source is a different or recreated view.
+    fn set_source(&mut self, source: &wgpu::TextureView, device: &wgpu::Device);
+    fn render(
+        &mut self,
+        dest: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    );
+    /// Rebuilds the pipeline from new SPIR-V. On error, the current pipeline is kept.
+    #[cfg(feature = "hot-reload")]
+    fn set_shaders(
+        &mut self,
+        device: &wgpu::Device,
+        vs_words: &[u32],
+        fs_words: &[u32],
+    ) -> Result<(), reflect::InterfaceError>;
+    /// Number of GPU objects created after construction so far.
+    fn allocations(&self) -> usize {
+        0
+    }
+}
+
+/// Which view a pass was last given as its source.
+#[derive(Clone, Copy, PartialEq)]
+enum Source {
+    /// From `render_from`, with the generation the caller gave.
+    External(u64),
+    /// One of the chain's targets, with the chain's generation.
+    Target(usize, u64),
+}
+
+/// What a pass reads from or writes to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Slot {
+    /// The view given to `render_from`.
+    Source,
+    Target(usize),
+    /// The destination given to `render`.
+    Dest,
+}
+
+/// The input and output of each pass, `None` for the disabled ones. The passes
+/// ping-pong between the two targets; an external source takes the place of
+/// the first target, shifting the ping-pong by one.
+fn plan(enabled: &[bool], external_source: bool) -> Vec<Option<(Slot, Slot)>> {
+    let count = enabled.iter().filter(|&&enabled| enabled).count();
+    let shift = if external_source { 1 } else { 0 };
+    let mut step = 0;
+    enabled
+        .iter()
+        .map(|&enabled| {
+            if !enabled {
+                return None;
+            }
+            let input = match step {
+                0 if external_source => Slot::Source,
+                _ => Slot::Target((step + shift) % 2),
+            };
+            let output = if step + 1 == count {
+                Slot::Dest
+            } else {
+                Slot::Target((step + shift + 1) % 2)
+            };
+            step += 1;
+            Some((input, output))
+        })
+        .collect()
+}
+
+/// How many targets `plan` uses.
+fn targets_used(plan: &[Option<(Slot, Slot)>]) -> usize {
+    plan.iter()
+        .flatten()
+        .flat_map(|&(input, output)| vec![input, output])
+        .filter_map(|slot| match slot {
+            Slot::Target(index) => Some(index + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+struct Entry {
+    pass: Box<dyn PostProcessPass>,
+    enabled: bool,
+    source: Option<Source>,
+}
+
+struct Target {
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl Target {
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            format,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            label: Some("Post-processing target"),
+        });
+        let view = texture.create_default_view();
+        Target {
+            _texture: texture,
+            view,
+        }
+    }
+}
+
+/// Runs the enabled passes in order. The scene is rendered into `input_view`,
+/// and each pass reads the output of the previous one, the last writing to the
+/// destination given to `render`.
+///
+/// Intermediate images ping-pong between two textures, allocated on first use:
+/// with `render_from` and a single pass, there are none.
+pub struct PostProcessChain {
+    entries: Vec<Entry>,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    /// The input, or the first pass' output with `render_from`, then the second ping-pong texture.
+    targets: Vec<Target>,
+    /// Bumped whenever the targets are recreated.
+    generation: u64,
+}
+
+impl PostProcessChain {
+    pub fn new(format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        PostProcessChain {
+            entries: Vec::new(),
+            format,
+            width,
+            height,
+            targets: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    /// Appends an enabled pass, which should already be sized for the chain.
+    pub fn push(&mut self, pass: Box<dyn PostProcessPass>) {
+        self.entries.push(Entry {
+            pass,
+            enabled: true,
+            source: None,
+        });
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostProcessPass>> {
+        let index = self.position(name)?;
+        Some(self.entries.remove(index).pass)
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.pass.name() == name)
+    }
+
+    /// Names of all passes, enabled or not, in order.
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.entries.iter().map(|entry| entry.pass.name()).collect()
+    }
+
+    pub fn pass_mut(&mut self, name: &str) -> Option<&mut dyn PostProcessPass> {
+        let index = self.position(name)?;
+        Some(&mut *self.entries[index].pass)
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.position(name).map_or(false, |index| self.entries[index].enabled)
+    }
+
+    /// Returns false if there is no pass with this name.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.position(name) {
+            Some(index) => {
+                self.entries[index].enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves a pass to `index` in the order, shifting the others.
+    /// Returns false if there is no pass with this name.
+    pub fn move_pass(&mut self, name: &str, index: usize) -> bool {
+        match self.position(name) {
+            Some(from) => {
+                let entry = self.entries.remove(from);
+                let index = index.min(self.entries.len());
+                self.entries.insert(index, entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether `render` does anything. When it doesn't, render the scene
+    /// straight to the destination instead of `input_view`.
+    pub fn is_active(&self) -> bool {
+        self.entries.iter().any(|entry| entry.enabled)
+    }
+
+    /// Where the scene should be rendered.
+    pub fn input_view(&mut self, device: &wgpu::Device) -> &wgpu::TextureView {
+        self.allocate_targets(1, device);
+        &self.targets[0].view
+    }
+
+    fn allocate_targets(&mut self, count: usize, device: &wgpu::Device) {
+        while self.targets.len() < count {
+            self.targets.push(Target::new(device, self.format, self.width, self.height));
+        }
+    }
+
+    pub fn allocations(&self) -> usize {
+        self.entries.iter().map(|entry| entry.pass.allocations()).sum()
+    }
+
+    /// A zero size, as given for minimized windows, is ignored.
+    pub fn resize(&mut self, width: u32, height: u32, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        for target in &mut self.targets {
+            *target = Target::new(device, self.format, width, height);
+        }
+        self.generation += 1;
+        for entry in &mut self.entries {
+            entry.pass.resize(width, height, device, queue);
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        dest: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.run(dest, None, device, queue, encoder)
+    }
+
+    /// Same as `render`, reading from `source` instead of `input_view`.
+    /// `generation` must change whenever `source` is recreated, like `PassResources::generation`.
+    pub fn render_from(
+        &mut self,
+        dest: &wgpu::TextureView,
+        source: &wgpu::TextureView,
+        generation: u64,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.run(dest, Some((source, generation)), device, queue, encoder)
+    }
+
+    fn run(
+        &mut self,
+        dest: &wgpu::TextureView,
+        source: Option<(&wgpu::TextureView, u64)>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let enabled: Vec<bool> = self.entries.iter().map(|entry| entry.enabled).collect();
+        let plan = plan(&enabled, source.is_some());
+        self.allocate_targets(targets_used(&plan), device);
+
+        let targets = &self.targets;
+        let generation = self.generation;
+        for (entry, slots) in self.entries.iter_mut().zip(plan) {
+            let (input, output) = match slots {
+                Some(slots) => slots,
+                None => continue,
+            };
+            let (view, key) = match (input, source) {
+                (Slot::Target(index), _) => (&targets[index].view, Source::Target(index, generation)),
+                (_, Some((view, source_generation))) => (view, Source::External(source_generation)),
+                (_, None) => unreachable!("only planned with a source"),
+            };
+            if entry.source != Some(key) {
+                entry.pass.set_source(view, device);
+                entry.source = Some(key);
+            }
+            let output = match output {
+                Slot::Target(index) => &targets[index].view,
+                _ => dest,
+            };
+            entry.pass.render(output, device, queue, encoder);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Slot::*;
+
+    #[test]
+    fn ping_pong() {
+        assert_eq!(plan(&[true], false), vec![Some((Target(0), Dest))]);
+        assert_eq!(
+            plan(&[true, true], false),
+            vec![Some((Target(0), Target(1))), Some((Target(1), Dest))]
+        );
+        assert_eq!(
+            plan(&[true, true, true], false),
+            vec![
+                Some((Target(0), Target(1))),
+                Some((Target(1), Target(0))),
+                Some((Target(0), Dest)),
+            ]
+        );
+        assert_eq!(targets_used(&plan(&[true, true, true], false)), 2);
+    }
+
+    #[test]
+    fn external_source() {
+        assert_eq!(plan(&[true], true), vec![Some((Source, Dest))]);
+        assert_eq!(targets_used(&plan(&[true], true)), 0);
+        assert_eq!(
+            plan(&[true, true, true], true),
+            vec![
+                Some((Source, Target(0))),
+                Some((Target(0), Target(1))),
+                Some((Target(1), Dest)),
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_passes_are_skipped() {
+        assert_eq!(
+            plan(&[true, false, true], false),
+            vec![Some((Target(0), Target(1))), None, Some((Target(1), Dest))]
+        );
+        assert_eq!(plan(&[false, true, false], true), vec![None, Some((Source, Dest)), None]);
+        assert_eq!(targets_used(&plan(&[false, false], false)), 0);
+    }
+}
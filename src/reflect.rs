@@ -0,0 +1,524 @@
+//! Minimal SPIR-V reflection, to check bind group layouts against the
+//! resources shaders actually declare.
+
+use std::collections::HashMap;
+use std::fmt;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// opcodes
+const OP_DECORATE: u32 = 71;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_VARIABLE: u32 = 59;
+
+// decorations
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+// storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+/// The kind of resource a shader expects at a given binding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BindingKind {
+    UniformBuffer,
+    StorageBuffer,
+    Sampler,
+    SampledTexture {
+        dimension: wgpu::TextureViewDimension,
+        multisampled: bool,
+    },
+    StorageTexture {
+        dimension: wgpu::TextureViewDimension,
+    },
+    /// GLSL's `sampler2D` and friends, which wgpu can't bind.
+    CombinedImageSampler,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShaderBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub kind: BindingKind,
+}
+
+#[derive(Debug)]
+pub enum InterfaceError {
+    InvalidSpirv(&'static str),
+    /// The shader uses a binding the layout doesn't declare.
+    MissingBinding {
+        stage: wgpu::ShaderStage,
+        set: u32,
+        binding: u32,
+    },
+    /// The layout entry isn't visible from a stage which uses it.
+    NotVisible {
+        stage: wgpu::ShaderStage,
+        set: u32,
+        binding: u32,
+    },
+    /// The layout and the shader disagree on the type of a binding.
+    TypeMismatch {
+        stage: wgpu::ShaderStage,
+        set: u32,
+        binding: u32,
+        layout: wgpu::BindingType,
+        shader: BindingKind,
+    },
+    /// The layout declares a binding no shader uses.
+    UnusedBinding { set: u32, binding: u32 },
+    /// The layout entry is visible from stages which don't use it.
+    NotUsedByStage {
+        stage: wgpu::ShaderStage,
+        set: u32,
+        binding: u32,
+    },
+}
+
+impl fmt::Display for InterfaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterfaceError::InvalidSpirv(reason) => write!(f, "invalid SPIR-V: {}", reason),
+            InterfaceError::MissingBinding {
+                stage,
+                set,
+                binding,
+            } => write!(
+                f,
+                "{:?} shader uses set {} binding {}, which is missing from the layout",
+                stage, set, binding
+            ),
+            InterfaceError::NotVisible {
+                stage,
+                set,
+                binding,
+            } => write!(
+                f,
+                "set {} binding {} is used by the {:?} shader, but not visible from it",
+                set, binding, stage
+            ),
+            InterfaceError::TypeMismatch {
+                stage,
+                set,
+                binding,
+                layout,
+                shader,
+            } => write!(
+                f,
+                "set {} binding {}: the layout declares {:?}, but the {:?} shader declares {:?}",
+                set, binding, layout, stage, shader
+            ),
+            InterfaceError::UnusedBinding { set, binding } => {
+                write!(f, "set {} binding {} is in the layout, but no shader uses it", set, binding)
+            }
+            InterfaceError::NotUsedByStage {
+                stage,
+                set,
+                binding,
+            } => write!(
+                f,
+                "set {} binding {} is visible from {:?}, which doesn't use it",
+                set, binding, stage
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InterfaceError {}
+
+/// Turns the bytes of a little endian .spv file into SPIR-V words.
+pub fn spirv_words(bytes: &[u8]) -> Result<Vec<u32>, InterfaceError> {
+    if bytes.len() % 4 != 0 {
+        return Err(InterfaceError::InvalidSpirv("length is not a multiple of 4"));
+    }
+    let words: Vec<u32> = bytes
+        .chunks(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    if words.first() != Some(&SPIRV_MAGIC) {
+        return Err(InterfaceError::InvalidSpirv("bad magic number"));
+    }
+    Ok(words)
+}
+
+fn image_dimension(dim: u32, arrayed: bool) -> Result<wgpu::TextureViewDimension, InterfaceError> {
+    use wgpu::TextureViewDimension as D;
+    Ok(match (dim, arrayed) {
+        (0, false) => D::D1,
+        (1, false) => D::D2,
+        (1, true) => D::D2Array,
+        (2, false) => D::D3,
+        (3, false) => D::Cube,
+        (3, true) => D::CubeArray,
+        _ => return Err(InterfaceError::InvalidSpirv("unsupported image dimension")),
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Type {
+    Image {
+        dim: u32,
+        arrayed: bool,
+        multisampled: bool,
+        sampled: u32,
+    },
+    Sampler,
+    SampledImage,
+    Struct,
+    Array(u32),
+    Pointer(u32),
+}
+
+/// Lists the descriptor bindings declared by a SPIR-V module.
+pub fn reflect_bindings(words: &[u32]) -> Result<Vec<ShaderBinding>, InterfaceError> {
+    if words.len() < 5 || words[0] != SPIRV_MAGIC {
+        return Err(InterfaceError::InvalidSpirv("bad header"));
+    }
+
+    let mut types = HashMap::new();
+    let mut sets = HashMap::new();
+    let mut bindings = HashMap::new();
+    let mut blocks = HashMap::new();
+    // (id, pointer type, storage class)
+    let mut variables = Vec::new();
+
+    let mut cursor = 5;
+    while cursor < words.len() {
+        let word_count = (words[cursor] >> 16) as usize;
+        let opcode = words[cursor] & 0xffff;
+        if word_count == 0 || cursor + word_count > words.len() {
+            return Err(InterfaceError::InvalidSpirv("truncated instruction"));
+        }
+        let operands = &words[cursor + 1..cursor + word_count];
+
+        match opcode {
+            OP_DECORATE if operands.len() >= 2 => match operands[1] {
+                DECORATION_BINDING if operands.len() >= 3 => {
+                    bindings.insert(operands[0], operands[2]);
+                }
+                DECORATION_DESCRIPTOR_SET if operands.len() >= 3 => {
+                    sets.insert(operands[0], operands[2]);
+                }
+                DECORATION_BLOCK => {
+                    blocks.insert(operands[0], BindingKind::UniformBuffer);
+                }
+                DECORATION_BUFFER_BLOCK => {
+                    blocks.insert(operands[0], BindingKind::StorageBuffer);
+                }
+                _ => {}
+            },
+            OP_TYPE_IMAGE if operands.len() >= 7 => {
+                types.insert(
+                    operands[0],
+                    Type::Image {
+                        dim: operands[2],
+                        arrayed: operands[4] != 0,
+                        multisampled: operands[5] != 0,
+                        sampled: operands[6],
+                    },
+                );
+            }
+            OP_TYPE_SAMPLER if !operands.is_empty() => {
+                types.insert(operands[0], Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE if !operands.is_empty() => {
+                types.insert(operands[0], Type::SampledImage);
+            }
+            OP_TYPE_STRUCT if !operands.is_empty() => {
+                types.insert(operands[0], Type::Struct);
+            }
+            OP_TYPE_ARRAY | OP_TYPE_RUNTIME_ARRAY if operands.len() >= 2 => {
+                types.insert(operands[0], Type::Array(operands[1]));
+            }
+            OP_TYPE_POINTER if operands.len() >= 3 => {
+                types.insert(operands[0], Type::Pointer(operands[2]));
+            }
+            OP_VARIABLE if operands.len() >= 3 => {
+                variables.push((operands[1], operands[0], operands[2]));
+            }
+            _ => {}
+        }
+        cursor += word_count;
+    }
+
+    let mut result = Vec::new();
+    for (id, pointer_type, storage_class) in variables {
+        let binding = match (sets.get(&id), bindings.get(&id)) {
+            (Some(&set), Some(&binding)) => (set, binding),
+            _ => continue,
+        };
+
+        let mut type_id = match types.get(&pointer_type) {
+            Some(Type::Pointer(pointee)) => *pointee,
+            _ => return Err(InterfaceError::InvalidSpirv("variable type is not a pointer")),
+        };
+        // arrays of resources bind like their element
+        while let Some(Type::Array(element)) = types.get(&type_id) {
+            type_id = *element;
+        }
+
+        let kind = match (storage_class, types.get(&type_id)) {
+            (STORAGE_UNIFORM, Some(Type::Struct)) => match blocks.get(&type_id) {
+                Some(kind) => *kind,
+                None => return Err(InterfaceError::InvalidSpirv("uniform struct without a block decoration")),
+            },
+            (STORAGE_STORAGE_BUFFER, Some(Type::Struct)) => BindingKind::StorageBuffer,
+            (STORAGE_UNIFORM_CONSTANT, Some(Type::Sampler)) => BindingKind::Sampler,
+            (STORAGE_UNIFORM_CONSTANT, Some(Type::SampledImage)) => BindingKind::CombinedImageSampler,
+            (
+                STORAGE_UNIFORM_CONSTANT,
+                Some(Type::Image {
+                    dim,
+                    arrayed,
+                    multisampled,
+                    sampled,
+                }),
+            ) => {
+                let dimension = image_dimension(*dim, *arrayed)?;
+                if *sampled == 2 {
+                    BindingKind::StorageTexture { dimension }
+                } else {
+                    BindingKind::SampledTexture {
+                        dimension,
+                        multisampled: *multisampled,
+                    }
+                }
+            }
+            _ => return Err(InterfaceError::InvalidSpirv("unsupported resource type")),
+        };
+
+        result.push(ShaderBinding {
+            set: binding.0,
+            binding: binding.1,
+            kind,
+        });
+    }
+
+    Ok(result)
+}
+
+fn matches(layout: &wgpu::BindingType, shader: &BindingKind) -> bool {
+    match (layout, shader) {
+        (wgpu::BindingType::UniformBuffer { .. }, BindingKind::UniformBuffer) => true,
+        (wgpu::BindingType::StorageBuffer { .. }, BindingKind::StorageBuffer) => true,
+        (wgpu::BindingType::Sampler { .. }, BindingKind::Sampler) => true,
+        (
+            wgpu::BindingType::SampledTexture {
+                dimension,
+                multisampled,
+                ..
+            },
+            BindingKind::SampledTexture {
+                dimension: shader_dimension,
+                multisampled: shader_multisampled,
+            },
+        ) => dimension == shader_dimension && multisampled == shader_multisampled,
+        (
+            wgpu::BindingType::StorageTexture { dimension, .. },
+            BindingKind::StorageTexture {
+                dimension: shader_dimension,
+            },
+        ) => dimension == shader_dimension,
+        _ => false,
+    }
+}
+
+/// Checks that each binding used by the shaders is declared by the matching
+/// bind group layout, with a compatible type and visibility, and that each
+/// layout entry is only visible from the given stages which use it.
+/// `layouts[i]` holds the entries of the layout for set `i`.
+pub fn validate_layouts(
+    layouts: &[&[wgpu::BindGroupLayoutEntry]],
+    shaders: &[(wgpu::ShaderStage, &[u32])],
+) -> Result<(), InterfaceError> {
+    // (stage, set, binding)
+    let mut used = Vec::new();
+    for &(stage, words) in shaders {
+        for shader_binding in reflect_bindings(words)? {
+            let ShaderBinding { set, binding, kind } = shader_binding;
+            let entry = layouts
+                .get(set as usize)
+                .and_then(|entries| entries.iter().find(|entry| entry.binding == binding))
+                .ok_or(InterfaceError::MissingBinding {
+                    stage,
+                    set,
+                    binding,
+                })?;
+
+            if !entry.visibility.contains(stage) {
+                return Err(InterfaceError::NotVisible {
+                    stage,
+                    set,
+                    binding,
+                });
+            }
+            if !matches(&entry.ty, &kind) {
+                return Err(InterfaceError::TypeMismatch {
+                    stage,
+                    set,
+                    binding,
+                    layout: entry.ty.clone(),
+                    shader: kind,
+                });
+            }
+            used.push((stage, set, binding));
+        }
+    }
+
+    let stages = shaders
+        .iter()
+        .fold(wgpu::ShaderStage::empty(), |stages, &(stage, _)| stages | stage);
+    for (set, entries) in layouts.iter().enumerate() {
+        let set = set as u32;
+        for entry in entries.iter() {
+            let binding = entry.binding;
+            let users = used
+                .iter()
+                .filter(|&&(_, used_set, used_binding)| used_set == set && used_binding == binding)
+                .fold(wgpu::ShaderStage::empty(), |users, &(stage, _, _)| users | stage);
+            if users.is_empty() {
+                return Err(InterfaceError::UnusedBinding { set, binding });
+            }
+            let extra = (entry.visibility & stages) - users;
+            if !extra.is_empty() {
+                return Err(InterfaceError::NotUsedByStage {
+                    stage: extra,
+                    set,
+                    binding,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fxaa_fragment_bindings() {
+        let words = spirv_words(include_bytes!("fxaa.frag.spv")).unwrap();
+        let mut bindings = reflect_bindings(&words).unwrap();
+        bindings.sort_by_key(|b| (b.set, b.binding));
+        assert_eq!(
+            bindings,
+            vec![
+                ShaderBinding {
+                    set: 0,
+                    binding: 0,
+                    kind: BindingKind::SampledTexture {
+                        dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                },
+                ShaderBinding {
+                    set: 0,
+                    binding: 1,
+                    kind: BindingKind::UniformBuffer,
+                },
+                ShaderBinding {
+                    set: 1,
+                    binding: 0,
+                    kind: BindingKind::Sampler,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn array_texture_mismatch() {
+        let words = spirv_words(include_bytes!("fxaa.frag.spv")).unwrap();
+        let texture = wgpu::BindGroupLayoutEntry::new(
+            0,
+            wgpu::ShaderStage::FRAGMENT,
+            wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                component_type: wgpu::TextureComponentType::Float,
+                dimension: wgpu::TextureViewDimension::D2Array,
+            },
+        );
+        let locals = wgpu::BindGroupLayoutEntry::new(
+            1,
+            wgpu::ShaderStage::FRAGMENT,
+            wgpu::BindingType::UniformBuffer {
+                dynamic: false,
+                min_binding_size: None,
+            },
+        );
+        let sampler = wgpu::BindGroupLayoutEntry::new(
+            0,
+            wgpu::ShaderStage::FRAGMENT,
+            wgpu::BindingType::Sampler { comparison: false },
+        );
+
+        let result = validate_layouts(
+            &[&[texture, locals], &[sampler]],
+            &[(wgpu::ShaderStage::FRAGMENT, &words)],
+        );
+        match result {
+            Err(InterfaceError::TypeMismatch { set: 0, binding: 0, .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unused_bindings() {
+        let vs_words = spirv_words(include_bytes!("fxaa.vert.spv")).unwrap();
+        let fs_words = spirv_words(include_bytes!("blit.frag.spv")).unwrap();
+        let shaders = [
+            (wgpu::ShaderStage::VERTEX, &vs_words[..]),
+            (wgpu::ShaderStage::FRAGMENT, &fs_words[..]),
+        ];
+        let texture = wgpu::BindGroupLayoutEntry::new(
+            0,
+            wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+            wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                component_type: wgpu::TextureComponentType::Float,
+                dimension: wgpu::TextureViewDimension::D2,
+            },
+        );
+        let sampler = wgpu::BindGroupLayoutEntry::new(
+            1,
+            wgpu::ShaderStage::FRAGMENT,
+            wgpu::BindingType::Sampler { comparison: false },
+        );
+        let extra = wgpu::BindGroupLayoutEntry::new(
+            2,
+            wgpu::ShaderStage::FRAGMENT,
+            wgpu::BindingType::Sampler { comparison: false },
+        );
+
+        match validate_layouts(&[&[texture.clone(), sampler.clone()]], &shaders) {
+            Err(InterfaceError::NotUsedByStage { set: 0, binding: 0, .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let texture = wgpu::BindGroupLayoutEntry {
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ..texture
+        };
+        match validate_layouts(&[&[texture.clone(), sampler.clone(), extra]], &shaders) {
+            Err(InterfaceError::UnusedBinding { set: 0, binding: 2 }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(validate_layouts(&[&[texture, sampler]], &shaders).is_ok());
+    }
+
+    #[test]
+    fn invalid_words() {
+        assert!(spirv_words(&[0x03, 0x02, 0x23, 0x07, 0x00]).is_err());
+        assert!(spirv_words(&[0x07, 0x23, 0x02, 0x03]).is_err());
+        assert_eq!(spirv_words(&[0x03, 0x02, 0x23, 0x07]).unwrap(), vec![SPIRV_MAGIC]);
+    }
+}
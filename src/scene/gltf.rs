@@ -0,0 +1,487 @@
+//! glTF 2.0, as a .gltf file with its resources or a single .glb file.
+//!
+//! Skins and morph targets are not supported: such meshes are drawn in their rest pose.
+
+use super::{Image, Node, Scene, SceneCamera, SceneClip, SceneError, SceneMesh};
+use crate::animation::{Clip, Easing, Interpolate, Keyframe, Pose, Track};
+use crate::box_renderer::Vertex;
+use crate::mesh::Mesh;
+use ::gltf::animation::util::ReadOutputs;
+use ::gltf::animation::Interpolation;
+use ::gltf::buffer::Data;
+use cgmath::{Deg, Quaternion, Rotation3, Vector3};
+use std::collections::HashMap;
+use std::path::Path;
+
+pub(super) fn load(path: &Path) -> Result<Scene, SceneError> {
+    let (document, buffers, images) = ::gltf::import(path)?;
+    check_extensions(&document)?;
+    import(&document, &buffers, images)
+}
+
+/// No extension is supported, but the optional ones can be ignored.
+fn check_extensions(document: &::gltf::Document) -> Result<(), SceneError> {
+    match document.extensions_required().next() {
+        Some(name) => Err(SceneError::UnsupportedExtension(name.to_string())),
+        None => Ok(()),
+    }
+}
+
+fn import(
+    document: &::gltf::Document,
+    buffers: &[Data],
+    images: Vec<::gltf::image::Data>,
+) -> Result<Scene, SceneError> {
+    let images = images
+        .into_iter()
+        .enumerate()
+        .map(|(index, image)| convert_image(index, image))
+        .collect::<Result<_, _>>()?;
+
+    let mut meshes = Vec::new();
+    let mut mesh_ids = HashMap::new();
+    for mesh in document.meshes() {
+        let ids = load_mesh(&mesh, buffers, &mut meshes)?;
+        mesh_ids.insert(mesh.index(), ids);
+    }
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or(SceneError::NoScene)?;
+    // glTF is Y up, rotate it to Z up
+    let mut nodes = vec![Node {
+        name: None,
+        parent: None,
+        rest: Pose {
+            rotation: Quaternion::from_angle_x(Deg(90.)),
+            ..Pose::default()
+        },
+        meshes: Vec::new(),
+        camera: None,
+    }];
+    let mut node_ids = HashMap::new();
+    for node in scene.nodes() {
+        add_node(&node, 0, &mesh_ids, &mut nodes, &mut node_ids);
+    }
+
+    let clips = document
+        .animations()
+        .map(|animation| load_animation(&animation, buffers, &node_ids))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Scene {
+        nodes,
+        meshes,
+        images,
+        clips,
+    })
+}
+
+fn convert_image(index: usize, image: ::gltf::image::Data) -> Result<Image, SceneError> {
+    use ::gltf::image::Format;
+
+    let rgba = match image.format {
+        Format::R8G8B8A8 => image.pixels,
+        Format::R8G8B8 => {
+            let mut rgba = Vec::with_capacity(image.pixels.len() / 3 * 4);
+            for rgb in image.pixels.chunks_exact(3) {
+                rgba.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+            }
+            rgba
+        }
+        Format::R8 => {
+            let mut rgba = Vec::with_capacity(image.pixels.len() * 4);
+            for &luma in &image.pixels {
+                rgba.extend_from_slice(&[luma, luma, luma, 255]);
+            }
+            rgba
+        }
+        format => {
+            return Err(SceneError::UnsupportedImageFormat {
+                image: index,
+                format: format!("{:?}", format),
+            })
+        }
+    };
+    Ok(Image {
+        width: image.width,
+        height: image.height,
+        rgba,
+    })
+}
+
+/// Adds a scene mesh per primitive, returning their indices.
+/// The base color factor is baked into the vertex colors.
+fn load_mesh(mesh: &::gltf::Mesh, buffers: &[Data], meshes: &mut Vec<SceneMesh>) -> Result<Vec<usize>, SceneError> {
+    let mut ids = Vec::new();
+    for primitive in mesh.primitives() {
+        if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+            return Err(SceneError::UnsupportedPrimitive {
+                mesh: mesh.index(),
+                mode: format!("{:?}", primitive.mode()),
+            });
+        }
+        if primitive.morph_targets().next().is_some() {
+            log::warn!("Mesh {} has morph targets, which are not supported", mesh.index());
+        }
+
+        let reader = primitive.reader(|buffer| Some(buffers[buffer.index()].0.as_slice()));
+        let positions: Vec<[f32; 3]> = reader
+            .read_positions()
+            .ok_or(SceneError::MissingPositions { mesh: mesh.index() })?
+            .collect();
+        let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(Iterator::collect);
+        let tex_coords: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|tex_coords| tex_coords.into_f32().collect());
+        let colors: Option<Vec<[f32; 4]>> = reader.read_colors(0).map(|colors| colors.into_rgba_f32().collect());
+
+        let pbr = primitive.material().pbr_metallic_roughness();
+        let base_color = pbr.base_color_factor();
+        let vertices = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &pos)| {
+                let mut vertex = Vertex::new(
+                    pos,
+                    tex_coords.as_ref().map_or([0., 0.], |tex_coords| tex_coords[i]),
+                    normals.as_ref().map_or([0., 0., 0.], |normals| normals[i]),
+                );
+                let color = colors.as_ref().map_or([1.; 4], |colors| colors[i]);
+                for (channel, (value, factor)) in vertex.color.iter_mut().zip(color.iter().zip(&base_color)) {
+                    *channel = value * factor;
+                }
+                vertex
+            })
+            .collect();
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        let mut scene_mesh = Mesh::new(vertices, indices);
+        if normals.is_none() {
+            scene_mesh.compute_normals();
+        }
+        let texture = pbr.base_color_texture().map(|info| {
+            if info.tex_coord() != 0 {
+                log::warn!("Mesh {}: only the first texture coordinates are supported", mesh.index());
+            }
+            info.texture().source().index()
+        });
+        meshes.push(SceneMesh {
+            mesh: scene_mesh,
+            texture,
+        });
+        ids.push(meshes.len() - 1);
+    }
+    Ok(ids)
+}
+
+/// glTF quaternions are stored as [x, y, z, w].
+fn quaternion(xyzw: [f32; 4]) -> Quaternion<f32> {
+    Quaternion::new(xyzw[3], xyzw[0], xyzw[1], xyzw[2])
+}
+
+/// Adds `node` and its children, depth first, so that parents come first.
+fn add_node(
+    node: &::gltf::Node,
+    parent: usize,
+    mesh_ids: &HashMap<usize, Vec<usize>>,
+    nodes: &mut Vec<Node>,
+    node_ids: &mut HashMap<usize, usize>,
+) {
+    if node_ids.contains_key(&node.index()) {
+        log::warn!("Node {} has several parents, keeping the first one", node.index());
+        return;
+    }
+    if node.skin().is_some() {
+        log::warn!("Node {} is skinned, which is not supported", node.index());
+    }
+
+    let (translation, rotation, scale) = node.transform().decomposed();
+    let camera = node.camera().map(|camera| match camera.projection() {
+        ::gltf::camera::Projection::Perspective(perspective) => SceneCamera::Perspective {
+            yfov: perspective.yfov(),
+        },
+        ::gltf::camera::Projection::Orthographic(orthographic) => SceneCamera::Orthographic {
+            ymag: orthographic.ymag(),
+        },
+    });
+    nodes.push(Node {
+        name: node.name().map(str::to_string),
+        parent: Some(parent),
+        rest: Pose {
+            translation: translation.into(),
+            rotation: quaternion(rotation),
+            scale: scale.into(),
+            ..Pose::default()
+        },
+        meshes: node
+            .mesh()
+            .map_or_else(Vec::new, |mesh| mesh_ids[&mesh.index()].clone()),
+        camera,
+    });
+
+    let id = nodes.len() - 1;
+    node_ids.insert(node.index(), id);
+    for child in node.children() {
+        add_node(&child, id, mesh_ids, nodes, node_ids);
+    }
+}
+
+/// Cubic spline outputs are stored as (in tangent, value, out tangent) triplets:
+/// only the values are kept, and interpolated linearly.
+fn track<T: Interpolate>(
+    times: &[f32],
+    values: Vec<T>,
+    interpolation: Interpolation,
+) -> Result<Track<T>, SceneError> {
+    let (values, easing) = match interpolation {
+        Interpolation::Step => (values, Easing::Step),
+        Interpolation::Linear => (values, Easing::Linear),
+        Interpolation::CubicSpline => (values.chunks_exact(3).map(|triplet| triplet[1]).collect(), Easing::Linear),
+    };
+    let keyframes = times
+        .iter()
+        .zip(values)
+        .map(|(&time, value)| Keyframe::new(time, value, easing))
+        .collect();
+    Ok(Track::try_new(keyframes)?)
+}
+
+/// Channels targeting nodes outside of the loaded scene are dropped.
+fn load_animation(
+    animation: &::gltf::Animation,
+    buffers: &[Data],
+    node_ids: &HashMap<usize, usize>,
+) -> Result<SceneClip, SceneError> {
+    let mut clips: HashMap<usize, Clip> = HashMap::new();
+    let mut cubic = false;
+    for channel in animation.channels() {
+        let node = match node_ids.get(&channel.target().node().index()) {
+            Some(&node) => node,
+            None => continue,
+        };
+        let reader = channel.reader(|buffer| Some(buffers[buffer.index()].0.as_slice()));
+        let times: Vec<f32> = match reader.read_inputs() {
+            Some(times) => times.collect(),
+            None => continue,
+        };
+        let interpolation = channel.sampler().interpolation();
+        cubic |= interpolation == Interpolation::CubicSpline;
+
+        let clip = clips.entry(node).or_default();
+        match reader.read_outputs() {
+            Some(ReadOutputs::Translations(values)) => {
+                clip.translation = Some(track(&times, values.map(Vector3::from).collect(), interpolation)?)
+            }
+            Some(ReadOutputs::Rotations(values)) => {
+                clip.rotation = Some(track(&times, values.into_f32().map(quaternion).collect(), interpolation)?)
+            }
+            Some(ReadOutputs::Scales(values)) => {
+                clip.scale = Some(track(&times, values.map(Vector3::from).collect(), interpolation)?)
+            }
+            Some(ReadOutputs::MorphTargetWeights(_)) => {
+                log::warn!("Animation {}: morph target weights are not supported", animation.index())
+            }
+            None => {}
+        }
+    }
+    if cubic {
+        log::warn!("Animation {}: cubic splines are interpolated linearly", animation.index());
+    }
+
+    let mut channels: Vec<(usize, Clip)> = clips.into_iter().collect();
+    channels.sort_by_key(|&(node, _)| node);
+    Ok(SceneClip {
+        name: animation.name().map(str::to_string),
+        channels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(json: &str) -> ::gltf::Document {
+        ::gltf::Gltf::from_slice(json.as_bytes()).unwrap().document
+    }
+
+    /// A base64 data URI holding `values` as little endian floats.
+    fn data_uri(values: &[f32]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        let bytes: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect();
+        let mut uri = String::from("data:application/octet-stream;base64,");
+        for chunk in bytes.chunks(3) {
+            let bits = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    uri.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    uri.push('=');
+                }
+            }
+        }
+        uri
+    }
+
+    /// Loads a .gltf file whose buffers are data URIs, going through a temporary file.
+    fn load_json(name: &str, json: &str) -> Scene {
+        let path = std::env::temp_dir().join(format!("anima-{}-{}.gltf", name, std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let scene = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        scene.unwrap()
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        let close = actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-5);
+        assert!(close, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn required_extension() {
+        let document = document(
+            r#"{
+                "asset": { "version": "2.0" },
+                "extensionsUsed": ["KHR_draco_mesh_compression"],
+                "extensionsRequired": ["KHR_draco_mesh_compression"]
+            }"#,
+        );
+        match check_extensions(&document) {
+            Err(SceneError::UnsupportedExtension(name)) => assert_eq!(name, "KHR_draco_mesh_compression"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn node_hierarchy() {
+        let document = document(
+            r#"{
+                "asset": { "version": "2.0" },
+                "scene": 0,
+                "scenes": [{ "nodes": [0] }],
+                "nodes": [
+                    { "translation": [1, 0, 0], "children": [1] },
+                    { "translation": [0, 2, 0] }
+                ]
+            }"#,
+        );
+        let scene = import(&document, &[], Vec::new()).unwrap();
+        assert_eq!(scene.nodes.len(), 3);
+        assert_eq!(scene.nodes[2].parent, Some(1));
+
+        // (1, 2, 0) in glTF, Y up
+        let child = scene.world_matrices(None, 0.)[2].w;
+        for (value, expected) in [child.x, child.y, child.z].iter().zip(&[1., 0., 2.]) {
+            assert!((value - expected).abs() < 1e-5, "{:?}", child);
+        }
+    }
+    #[test]
+    fn animation_channels() {
+        let times = [0., 1.];
+        // (in tangent, value, out tangent) per keyframe
+        let translations = [9., 9., 9., 1., 2., 3., 9., 9., 9., 9., 9., 9., 4., 5., 6., 9., 9., 9.];
+        // 90° about Z, as [x, y, z, w]
+        let half = 0.5f32.sqrt();
+        let rotations = [0., 0., 0., 1., 0., 0., half, half];
+        let data = [&times[..], &translations, &rotations].concat();
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{}, {}],
+            "buffers": [{ "byteLength": 112, "uri": "URI" }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 8 },
+                { "buffer": 0, "byteOffset": 8, "byteLength": 72 },
+                { "buffer": 0, "byteOffset": 80, "byteLength": 32 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0], "max": [1] },
+                { "bufferView": 1, "componentType": 5126, "count": 6, "type": "VEC3" },
+                { "bufferView": 2, "componentType": 5126, "count": 2, "type": "VEC4" }
+            ],
+            "animations": [{
+                "samplers": [
+                    { "input": 0, "output": 1, "interpolation": "CUBICSPLINE" },
+                    { "input": 0, "output": 2, "interpolation": "LINEAR" }
+                ],
+                "channels": [
+                    { "sampler": 0, "target": { "node": 0, "path": "translation" } },
+                    { "sampler": 1, "target": { "node": 0, "path": "rotation" } },
+                    { "sampler": 1, "target": { "node": 1, "path": "rotation" } }
+                ]
+            }]
+        }"#
+        .replace("URI", &data_uri(&data));
+        let scene = load_json("animation", &json);
+
+        // node 1 isn't in the scene, and the root takes index 0
+        let channels = &scene.clips[0].channels;
+        assert_eq!(channels.len(), 1);
+        let (node, clip) = &channels[0];
+        assert_eq!(*node, 1);
+
+        let translation = clip.translation.as_ref().unwrap();
+        let midway: [f32; 3] = translation.sample(0.5).unwrap().into();
+        assert_close(&midway, &[2.5, 3.5, 4.5]);
+        let end: [f32; 3] = translation.sample(1.).unwrap().into();
+        assert_close(&end, &[4., 5., 6.]);
+
+        let rotation = clip.rotation.as_ref().unwrap().sample(1.).unwrap();
+        assert_close(&[rotation.s, rotation.v.x, rotation.v.y, rotation.v.z], &[half, 0., 0., half]);
+    }
+
+    #[test]
+    fn base_color_factor_times_vertex_colors() {
+        let positions = [0., 0., 0., 1., 0., 0., 0., 1., 0.];
+        let colors = [1., 0.5, 0.2, 1., 0., 1., 1., 1., 1., 1., 1., 0.];
+        let data = [&positions[..], &colors].concat();
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "materials": [{ "pbrMetallicRoughness": { "baseColorFactor": [0.5, 1, 1, 0.5] } }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "COLOR_0": 1 }, "material": 0 }] }],
+            "buffers": [{ "byteLength": 84, "uri": "URI" }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 48 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC4" }
+            ]
+        }"#
+        .replace("URI", &data_uri(&data));
+        let scene = load_json("colors", &json);
+
+        let vertices = &scene.meshes[0].mesh.vertices;
+        assert_close(&vertices[0].color, &[0.5, 0.5, 0.2, 0.5]);
+        assert_close(&vertices[1].color, &[0., 1., 1., 0.5]);
+        assert_close(&vertices[2].color, &[0.5, 1., 1., 0.]);
+    }
+
+    #[test]
+    fn images_become_rgba() {
+        use ::gltf::image::{Data, Format};
+
+        let image = |format, pixels| Data {
+            pixels,
+            format,
+            width: 2,
+            height: 1,
+        };
+        let luma = convert_image(0, image(Format::R8, vec![10, 20])).unwrap();
+        assert_eq!(luma.rgba, vec![10, 10, 10, 255, 20, 20, 20, 255]);
+        let rgb = convert_image(0, image(Format::R8G8B8, vec![1, 2, 3, 4, 5, 6])).unwrap();
+        assert_eq!(rgb.rgba, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+        assert!(convert_image(0, image(Format::R16, vec![0; 4])).is_err());
+    }
+}
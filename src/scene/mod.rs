@@ -0,0 +1,301 @@
+//! Scenes of meshes placed by a node hierarchy, with cameras and animation clips,
+//! and their playback through a `BoxRenderer`.
+
+#[cfg(feature = "gltf")]
+mod gltf;
+
+use crate::animation::{AnimationError, Clip, Pose};
+use crate::box_renderer::{BoxInstance, BoxRenderer};
+use crate::camera::{Camera, Projection};
+use crate::mesh::Mesh;
+use crate::texture::{SamplerOptions, Texture};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, Transform, Vector3};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum SceneError {
+    #[cfg(feature = "gltf")]
+    Gltf(::gltf::Error),
+    /// An extension the file requires to be displayed correctly.
+    UnsupportedExtension(String),
+    /// Only triangle lists are supported.
+    UnsupportedPrimitive { mesh: usize, mode: String },
+    MissingPositions { mesh: usize },
+    UnsupportedImageFormat { image: usize, format: String },
+    NoScene,
+    UnknownFormat(PathBuf),
+    Animation(AnimationError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "gltf")]
+            SceneError::Gltf(err) => write!(f, "{}", err),
+            SceneError::UnsupportedExtension(name) => write!(f, "unsupported required extension: {}", name),
+            SceneError::UnsupportedPrimitive { mesh, mode } => {
+                write!(f, "mesh {}: unsupported primitive mode {}, only triangles are", mesh, mode)
+            }
+            SceneError::MissingPositions { mesh } => write!(f, "mesh {}: primitive without positions", mesh),
+            SceneError::UnsupportedImageFormat { image, format } => {
+                write!(f, "image {}: unsupported pixel format {}", image, format)
+            }
+            SceneError::NoScene => write!(f, "the file has no scene"),
+            SceneError::UnknownFormat(path) => write!(f, "unknown scene format: {}", path.display()),
+            SceneError::Animation(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "gltf")]
+            SceneError::Gltf(err) => Some(err),
+            SceneError::Animation(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "gltf")]
+impl From<::gltf::Error> for SceneError {
+    fn from(err: ::gltf::Error) -> Self {
+        SceneError::Gltf(err)
+    }
+}
+
+impl From<AnimationError> for SceneError {
+    fn from(err: AnimationError) -> Self {
+        SceneError::Animation(err)
+    }
+}
+
+/// Tightly packed 8 bits per channel RGBA pixels, uploaded when the scene is played.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+/// Geometry drawn with a single material.
+#[derive(Clone, Debug)]
+pub struct SceneMesh {
+    pub mesh: Mesh,
+    /// Index in `Scene::images`.
+    pub texture: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SceneCamera {
+    /// Vertical field of view, in radians.
+    Perspective { yfov: f32 },
+    /// Half the visible height, in world units.
+    Orthographic { ymag: f32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: Option<String>,
+    /// Parents come before their children in `Scene::nodes`.
+    pub parent: Option<usize>,
+    /// The transform when no clip animates the node.
+    pub rest: Pose,
+    /// Indices in `Scene::meshes`.
+    pub meshes: Vec<usize>,
+    /// Looks down the node's -Z axis, Y being up.
+    pub camera: Option<SceneCamera>,
+}
+
+/// Node animations, played together.
+#[derive(Clone, Debug)]
+pub struct SceneClip {
+    pub name: Option<String>,
+    /// Node indices and their clips.
+    pub channels: Vec<(usize, Clip)>,
+}
+
+impl SceneClip {
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .fold(0., |duration: f32, (_, clip)| duration.max(clip.duration()))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    pub nodes: Vec<Node>,
+    pub meshes: Vec<SceneMesh>,
+    pub images: Vec<Image>,
+    pub clips: Vec<SceneClip>,
+}
+
+impl Scene {
+    /// Loads a .gltf or .glb file, picked by extension. Needs the `gltf` feature.
+    pub fn load(path: &Path) -> Result<Self, SceneError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            #[cfg(feature = "gltf")]
+            Some("gltf") | Some("glb") => self::gltf::load(path),
+            _ => Err(SceneError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    /// The transform of each node, with `clip` sampled at `time` seconds.
+    pub fn world_matrices(&self, clip: Option<usize>, time: f32) -> Vec<Matrix4<f32>> {
+        let mut poses: Vec<Pose> = self.nodes.iter().map(|node| node.rest).collect();
+        if let Some(clip) = clip {
+            for (node, channel) in &self.clips[clip].channels {
+                poses[*node] = channel.sample(time, &self.nodes[*node].rest);
+            }
+        }
+
+        let mut matrices: Vec<Matrix4<f32>> = Vec::with_capacity(self.nodes.len());
+        for (node, pose) in self.nodes.iter().zip(poses) {
+            let matrix = match node.parent {
+                Some(parent) => matrices[parent] * pose.matrix(),
+                None => pose.matrix(),
+            };
+            matrices.push(matrix);
+        }
+        matrices
+    }
+}
+
+/// Draws a scene with a `BoxRenderer`, looping one of its clips.
+pub struct ScenePlayer {
+    scene: Scene,
+    /// The renderer's index of each scene mesh.
+    draws: Vec<usize>,
+    clip: Option<usize>,
+    time: f32,
+    pub playing: bool,
+}
+
+impl ScenePlayer {
+    /// Uploads the meshes and their textures, and hides the renderer's main mesh.
+    /// Plays the first clip, if any.
+    pub fn new(scene: Scene, renderer: &mut BoxRenderer, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let draws = scene
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let texture = mesh.texture.map(|index| {
+                    let image = &scene.images[index];
+                    Texture::from_rgba(
+                        device,
+                        queue,
+                        image.width,
+                        image.height,
+                        &image.rgba,
+                        &SamplerOptions::default(),
+                    )
+                });
+                renderer.add_mesh(&mesh.mesh, texture, device, queue)
+            })
+            .collect();
+        renderer.set_instances(&[], device, queue);
+
+        ScenePlayer {
+            clip: if scene.clips.is_empty() { None } else { Some(0) },
+            scene,
+            draws,
+            time: 0.,
+            playing: true,
+        }
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn clip(&self) -> Option<usize> {
+        self.clip
+    }
+
+    /// Plays a clip from its start, or shows the rest pose with None.
+    pub fn set_clip(&mut self, clip: Option<usize>) {
+        self.clip = clip;
+        self.time = 0.;
+    }
+
+    /// Goes through the clips in order, then the rest pose.
+    pub fn next_clip(&mut self) {
+        let next = match self.clip {
+            None => 0,
+            Some(clip) => clip + 1,
+        };
+        self.set_clip(if next < self.scene.clips.len() { Some(next) } else { None });
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.playing && self.clip.is_some()
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        if self.playing {
+            self.time += dt.as_secs_f32();
+        }
+    }
+
+    fn clip_time(&self) -> f32 {
+        let duration = self.clip.map_or(0., |clip| self.scene.clips[clip].duration());
+        if duration <= 0. {
+            return 0.;
+        }
+        self.time.rem_euclid(duration)
+    }
+
+    /// Places the instances of every mesh for the current time.
+    pub fn update(&self, renderer: &mut BoxRenderer, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let matrices = self.scene.world_matrices(self.clip, self.clip_time());
+        let mut instances = vec![Vec::new(); self.scene.meshes.len()];
+        for (node, matrix) in self.scene.nodes.iter().zip(&matrices) {
+            for &mesh in &node.meshes {
+                instances[mesh].push(BoxInstance::new(*matrix, [1., 1., 1., 1.]));
+            }
+        }
+        for (&draw, instances) in self.draws.iter().zip(&instances) {
+            renderer.set_mesh_instances(draw, instances, device, queue);
+        }
+    }
+
+    /// Moves `camera` to the first camera of the scene. Returns false if there is none.
+    pub fn apply_camera(&self, camera: &mut Camera) -> bool {
+        let (index, scene_camera) = match self
+            .scene
+            .nodes
+            .iter()
+            .enumerate()
+            .find_map(|(index, node)| node.camera.map(|camera| (index, camera)))
+        {
+            Some(found) => found,
+            None => return false,
+        };
+
+        let matrix = self.scene.world_matrices(self.clip, self.clip_time())[index];
+        let eye = matrix.transform_point(Point3::origin());
+        let forward = matrix.transform_vector(-Vector3::unit_z()).normalize();
+        // orbit around the point in front of the camera, as far as the origin
+        let target = eye + forward * eye.to_vec().magnitude().max(1.);
+        camera.look_at(eye, target);
+        match scene_camera {
+            SceneCamera::Perspective { yfov } => {
+                camera.set_projection(Projection::Perspective);
+                camera.set_fovy(Rad(yfov));
+            }
+            SceneCamera::Orthographic { ymag } => {
+                camera.set_projection(Projection::Orthographic);
+                camera.fit_height(2. * ymag);
+            }
+        }
+        true
+    }
+}
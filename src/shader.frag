@@ -1,7 +1,37 @@
 #version 450
 
+layout(location = 0) in vec4 v_Color;
+layout(location = 1) in vec2 v_TexCoord;
+layout(location = 2) in vec3 v_Normal;
+layout(location = 3) in vec3 v_WorldPos;
+
 layout(location = 0) out vec4 o_Target;
 
+layout(set = 0, binding = 1) uniform Lighting {
+    vec4 u_LightDirection; ///< Direction the light comes from.
+    vec4 u_LightColor;
+    vec4 u_Ambient;
+    vec4 u_BaseColor;
+    vec4 u_Specular; ///< rgb: specular color, a: shininess.
+    vec4 u_EyePosition;
+};
+
+// a white pixel when no texture is set
+layout(set = 1, binding = 0) uniform texture2D t_Color;
+layout(set = 1, binding = 1) uniform sampler s_Color;
+
 void main() {
-    o_Target = vec4(1.0, 0.0, 0.0, 1.0);
+    vec4 albedo = u_BaseColor * v_Color * texture(sampler2D(t_Color, s_Color), v_TexCoord);
+
+    vec3 normal = normalize(v_Normal);
+    vec3 light_dir = normalize(u_LightDirection.xyz);
+    vec3 view_dir = normalize(u_EyePosition.xyz - v_WorldPos);
+    vec3 half_dir = normalize(light_dir + view_dir);
+
+    float diffuse = max(dot(normal, light_dir), 0.0);
+    float specular = diffuse > 0.0 ? pow(max(dot(normal, half_dir), 0.0), u_Specular.a) : 0.0;
+
+    vec3 color = albedo.rgb * (u_Ambient.rgb + diffuse * u_LightColor.rgb)
+        + specular * u_Specular.rgb * u_LightColor.rgb;
+    o_Target = vec4(color, albedo.a);
 }
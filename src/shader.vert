@@ -1,11 +1,29 @@
 #version 450
 
 layout(location = 0) in vec4 a_Pos;
+layout(location = 1) in vec2 a_TexCoord;
+layout(location = 7) in vec3 a_Normal;
+layout(location = 8) in vec4 a_Color;
+
+// per instance
+layout(location = 2) in mat4 i_Transform;
+layout(location = 6) in vec4 i_Color;
+
+layout(location = 0) out vec4 v_Color;
+layout(location = 1) out vec2 v_TexCoord;
+layout(location = 2) out vec3 v_Normal;
+layout(location = 3) out vec3 v_WorldPos;
 
 layout(set = 0, binding = 0) uniform Locals {
     mat4 u_Transform;
 };
 
 void main() {
-    gl_Position = u_Transform * a_Pos;
+    vec4 world_pos = i_Transform * a_Pos;
+    v_Color = i_Color * a_Color;
+    v_TexCoord = a_TexCoord;
+    // the inverse transpose keeps normals perpendicular under non uniform scaling
+    v_Normal = transpose(inverse(mat3(i_Transform))) * a_Normal;
+    v_WorldPos = world_pos.xyz;
+    gl_Position = u_Transform * world_pos;
 }
@@ -0,0 +1,134 @@
+//! Runtime GLSL compilation, so shaders can be edited while the program runs.
+//! The shader sources are looked up in `ANIMA_SHADER_DIR`, or the crate's `src` directory.
+//!
+//! Sources are only checked for changes when a frame is rendered, so with on-demand
+//! frame pacing an edit shows up after the next input or resize.
+
+use crate::reflect::InterfaceError;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+#[derive(Debug)]
+pub enum ShaderError {
+    /// shaderc could not be initialized.
+    Compiler,
+    Io(PathBuf, io::Error),
+    UnknownStage(String),
+    Compile(String, shaderc::Error),
+    Interface(InterfaceError),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Compiler => write!(f, "cannot initialize the shader compiler"),
+            ShaderError::Io(path, err) => write!(f, "cannot read {}: {}", path.display(), err),
+            ShaderError::UnknownStage(name) => write!(f, "cannot guess the shader stage of {}", name),
+            ShaderError::Compile(name, err) => write!(f, "failed to compile {}: {}", name, err),
+            ShaderError::Interface(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Io(_, err) => Some(err),
+            ShaderError::Compile(_, err) => Some(err),
+            ShaderError::Interface(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<InterfaceError> for ShaderError {
+    fn from(err: InterfaceError) -> Self {
+        ShaderError::Interface(err)
+    }
+}
+
+pub struct ShaderLoader {
+    compiler: shaderc::Compiler,
+    dir: PathBuf,
+    /// Modification time of each source, when it was last compiled.
+    modified: HashMap<String, SystemTime>,
+}
+
+impl ShaderLoader {
+    pub fn new() -> Result<Self, ShaderError> {
+        let dir = std::env::var_os("ANIMA_SHADER_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src")));
+        Ok(ShaderLoader {
+            compiler: shaderc::Compiler::new().ok_or(ShaderError::Compiler)?,
+            dir,
+            modified: HashMap::new(),
+        })
+    }
+
+    fn modification_time(&self, name: &str) -> Option<SystemTime> {
+        fs::metadata(self.dir.join(name))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Compiles a GLSL source file to SPIR-V. The stage is deduced from the extension.
+    pub fn compile(&mut self, name: &str) -> Result<Vec<u32>, ShaderError> {
+        let kind = match name.rsplit('.').next() {
+            Some("vert") => shaderc::ShaderKind::Vertex,
+            Some("frag") => shaderc::ShaderKind::Fragment,
+            Some("comp") => shaderc::ShaderKind::Compute,
+            _ => return Err(ShaderError::UnknownStage(name.to_owned())),
+        };
+
+        let path = self.dir.join(name);
+        let source = fs::read_to_string(&path).map_err(|err| ShaderError::Io(path, err))?;
+        let artifact = self
+            .compiler
+            .compile_into_spirv(&source, kind, name, "main", None)
+            .map_err(|err| ShaderError::Compile(name.to_owned(), err))?;
+        Ok(artifact.as_binary().to_vec())
+    }
+
+    /// Returns whether any of the sources changed since it was last seen,
+    /// and remembers their current modification time.
+    fn changed(&mut self, names: &[&str]) -> bool {
+        let mut changed = false;
+        for name in names {
+            let modified = match self.modification_time(name) {
+                Some(modified) => modified,
+                None => continue,
+            };
+            if self.modified.insert((*name).to_owned(), modified) != Some(modified) {
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Recompiles a vertex / fragment shader pair when either source changed, and hands
+    /// the result to `rebuild`. Errors are logged, leaving the current pipeline in place.
+    /// The first call always compiles.
+    pub fn reload_if_changed<F>(&mut self, vs_name: &str, fs_name: &str, rebuild: F)
+    where
+        F: FnOnce(&[u32], &[u32]) -> Result<(), InterfaceError>,
+    {
+        if !self.changed(&[vs_name, fs_name]) {
+            return;
+        }
+
+        let result = self.compile(vs_name).and_then(|vs_words| {
+            let fs_words = self.compile(fs_name)?;
+            rebuild(&vs_words, &fs_words)?;
+            Ok(())
+        });
+        match result {
+            Ok(()) => log::info!("Reloaded {} and {}", vs_name, fs_name),
+            Err(err) => log::error!("Keeping the previous pipeline: {}", err),
+        }
+    }
+}
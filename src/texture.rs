@@ -0,0 +1,143 @@
+use std::borrow::Cow::Borrowed;
+#[cfg(feature = "image")]
+use std::path::Path;
+
+/// How a texture gets sampled.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerOptions {
+    pub address_mode: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        SamplerOptions {
+            address_mode: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/// A sampled RGBA texture, with its view and sampler.
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl Texture {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Uploads tightly packed 8 bits per channel RGBA pixels.
+    pub fn from_rgba(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        options: &SamplerOptions,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            format: Self::FORMAT,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            label: None,
+        });
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            data,
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: 4 * width,
+                rows_per_image: 0,
+            },
+            size,
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: options.address_mode,
+            address_mode_v: options.address_mode,
+            address_mode_w: options.address_mode,
+            mag_filter: options.mag_filter,
+            min_filter: options.min_filter,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Texture {
+            view: texture.create_default_view(),
+            texture,
+            sampler,
+        }
+    }
+
+    /// A single white pixel, which leaves colors untouched when sampled and multiplied.
+    pub fn white(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self::from_rgba(device, queue, 1, 1, &[255; 4], &SamplerOptions::default())
+    }
+
+    /// Loads a PNG or JPEG file.
+    #[cfg(feature = "image")]
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        options: &SamplerOptions,
+    ) -> image::ImageResult<Self> {
+        let image = image::open(path)?.to_rgba();
+        let (width, height) = image.dimensions();
+        Ok(Self::from_rgba(device, queue, width, height, &image, options))
+    }
+
+    /// Layout entries for a texture at `binding`, followed by its sampler.
+    pub fn layout_entries(binding: u32, visibility: wgpu::ShaderStage) -> [wgpu::BindGroupLayoutEntry; 2] {
+        [
+            wgpu::BindGroupLayoutEntry::new(
+                binding,
+                visibility,
+                wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    component_type: wgpu::TextureComponentType::Float,
+                    dimension: wgpu::TextureViewDimension::D2,
+                },
+            ),
+            wgpu::BindGroupLayoutEntry::new(
+                binding + 1,
+                visibility,
+                wgpu::BindingType::Sampler { comparison: false },
+            ),
+        ]
+    }
+
+    /// Creates a bind group matching `layout_entries(0, ..)`.
+    pub fn bind_group(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: Borrowed(&[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ]),
+            label: Some(Borrowed("Texture bind group")),
+        })
+    }
+}
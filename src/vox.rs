@@ -0,0 +1,564 @@
+//! MagicaVoxel .vox files: the models from SIZE and XYZI chunks, the RGBA palette,
+//! and where the nTRN, nGRP and nSHP scene graph places the models.
+//! Rotated nodes are rejected. Other chunks, like materials, are skipped.
+
+use crate::box_renderer::BoxInstance;
+use crate::mesh::Mesh;
+use crate::voxel::VoxelGrid;
+use cgmath::{Matrix4, Vector3};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Empty space between models, in voxels, when there is no scene graph to lay them out.
+const MODEL_GAP: f32 = 1.;
+
+#[derive(Debug)]
+pub enum VoxError {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VoxError::Io(err) => write!(f, "{}", err),
+            VoxError::Parse(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {}
+
+impl From<io::Error> for VoxError {
+    fn from(err: io::Error) -> Self {
+        VoxError::Io(err)
+    }
+}
+
+fn parse_error(message: impl Into<String>) -> VoxError {
+    VoxError::Parse(message.into())
+}
+
+/// How voxels get drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoxMode {
+    /// A box instance per voxel.
+    Boxes,
+    /// A single greedy mesh of the visible faces.
+    Mesh,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Voxel {
+    pub pos: [u8; 3],
+    /// Index in the palette, never 0.
+    pub color_index: u8,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VoxModel {
+    pub size: [u32; 3],
+    pub voxels: Vec<Voxel>,
+}
+
+/// A model placed by the scene graph.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoxPlacement {
+    /// Index in `VoxFile::models`.
+    pub model: usize,
+    /// Where the center of the model goes, in voxels.
+    pub translation: [i32; 3],
+}
+
+#[derive(Clone, Debug)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// RGBA colors, the first one being unused.
+    pub palette: [[u8; 4]; 256],
+    /// Empty for files without a scene graph, whose models are drawn side by side along X.
+    pub placements: Vec<VoxPlacement>,
+}
+
+/// A scene graph node, from an nTRN, nGRP or nSHP chunk.
+enum Node {
+    Transform { translation: [i32; 3], child: i32 },
+    Group(Vec<i32>),
+    Shape(usize),
+}
+
+/// Collects the models under node `id`, with the translations of the transforms above them.
+fn place(
+    nodes: &HashMap<i32, Node>,
+    id: i32,
+    translation: [i32; 3],
+    depth: usize,
+    placements: &mut Vec<VoxPlacement>,
+) -> Result<(), VoxError> {
+    if depth > nodes.len() {
+        return Err(parse_error("the scene graph has a cycle"));
+    }
+    match nodes.get(&id) {
+        Some(Node::Transform { translation: offset, child }) => {
+            let translation = [
+                translation[0] + offset[0],
+                translation[1] + offset[1],
+                translation[2] + offset[2],
+            ];
+            place(nodes, *child, translation, depth + 1, placements)
+        }
+        Some(Node::Group(children)) => children
+            .iter()
+            .try_for_each(|&child| place(nodes, child, translation, depth + 1, placements)),
+        Some(&Node::Shape(model)) => {
+            placements.push(VoxPlacement { model, translation });
+            Ok(())
+        }
+        None => Err(parse_error(format!("missing scene graph node {}", id))),
+    }
+}
+
+/// An nTRN `_t` value, "x y z".
+fn parse_translation(value: &str) -> Result<[i32; 3], VoxError> {
+    let error = || parse_error(format!("invalid translation {:?}", value));
+    let mut translation = [0; 3];
+    let mut parts = value.split_whitespace();
+    for axis in &mut translation {
+        *axis = parts.next().and_then(|part| part.parse().ok()).ok_or_else(error)?;
+    }
+    if parts.next().is_some() {
+        return Err(error());
+    }
+    Ok(translation)
+}
+
+/// MagicaVoxel's palette, for files without an RGBA chunk: a 6x6x6 color cube without black,
+/// then 10 shade ramps of red, green, blue and gray.
+fn default_palette() -> [[u8; 4]; 256] {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = [[0; 4]; 256];
+    let mut colors = palette.iter_mut().skip(1);
+    for &r in &CUBE {
+        for &g in &CUBE {
+            for &b in &CUBE {
+                if [r, g, b] != [0, 0, 0] {
+                    *colors.next().unwrap() = [r, g, b, 0xff];
+                }
+            }
+        }
+    }
+    for channel in 0..4 {
+        for &shade in &RAMP {
+            let mut color = [0, 0, 0, 0xff];
+            match channel {
+                3 => color[..3].copy_from_slice(&[shade; 3]),
+                _ => color[channel] = shade,
+            }
+            *colors.next().unwrap() = color;
+        }
+    }
+    palette
+}
+
+/// Reads chunks from a .vox file, keeping track of the offset for errors.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], VoxError> {
+        if self.data.len() - self.offset < count {
+            return Err(parse_error(format!("unexpected end of file at byte {}", self.offset)));
+        }
+        let bytes = &self.data[self.offset..self.offset + count];
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, VoxError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, VoxError> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn string(&mut self) -> Result<&'a str, VoxError> {
+        let len = self.u32()? as usize;
+        let offset = self.offset;
+        std::str::from_utf8(self.bytes(len)?).map_err(|_| parse_error(format!("invalid string at byte {}", offset)))
+    }
+
+    fn dict(&mut self) -> Result<HashMap<&'a str, &'a str>, VoxError> {
+        let count = self.u32()?;
+        (0..count).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset == self.data.len()
+    }
+}
+
+impl VoxFile {
+    pub fn load(path: &Path) -> Result<Self, VoxError> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, VoxError> {
+        let mut reader = Reader { data, offset: 0 };
+        if reader.bytes(4)? != b"VOX " {
+            return Err(parse_error("not a .vox file"));
+        }
+        let _version = reader.u32()?;
+        if reader.bytes(4)? != b"MAIN" {
+            return Err(parse_error("missing MAIN chunk"));
+        }
+        let content_size = reader.u32()? as usize;
+        let _children_size = reader.u32()?;
+        reader.bytes(content_size)?;
+
+        let mut file = VoxFile {
+            models: Vec::new(),
+            palette: default_palette(),
+            placements: Vec::new(),
+        };
+        // the models are SIZE and XYZI chunk pairs
+        let mut size = None;
+        let mut nodes = HashMap::new();
+        while !reader.is_empty() {
+            let id = reader.bytes(4)?;
+            let content_size = reader.u32()? as usize;
+            let children_size = reader.u32()? as usize;
+            let mut content = Reader {
+                data: reader.bytes(content_size)?,
+                offset: 0,
+            };
+            reader.bytes(children_size)?;
+
+            match id {
+                b"SIZE" => size = Some([content.u32()?, content.u32()?, content.u32()?]),
+                b"XYZI" => {
+                    let size = size.take().ok_or_else(|| parse_error("XYZI chunk without a SIZE chunk"))?;
+                    let count = content.u32()? as usize;
+                    let voxels = content
+                        .bytes(count * 4)?
+                        .chunks_exact(4)
+                        .map(|voxel| Voxel {
+                            pos: [voxel[0], voxel[1], voxel[2]],
+                            color_index: voxel[3],
+                        })
+                        .collect();
+                    file.models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    // color i of the chunk is palette index i + 1
+                    for (color, rgba) in file.palette[1..].iter_mut().zip(content.bytes(255 * 4)?.chunks_exact(4)) {
+                        color.copy_from_slice(rgba);
+                    }
+                }
+                b"nTRN" => {
+                    let id = content.i32()?;
+                    let _attributes = content.dict()?;
+                    let child = content.i32()?;
+                    let _reserved = content.i32()?;
+                    let _layer = content.i32()?;
+                    // animated transforms have more frames, the first one is shown
+                    let frame = match content.u32()? {
+                        0 => HashMap::new(),
+                        _ => content.dict()?,
+                    };
+                    // 4 is the identity
+                    if let Some(rotation) = frame.get("_r").filter(|rotation| rotation.trim() != "4") {
+                        return Err(parse_error(format!("node {} has rotation {}, which is not supported", id, rotation)));
+                    }
+                    let translation = match frame.get("_t") {
+                        Some(value) => parse_translation(value)?,
+                        None => [0; 3],
+                    };
+                    nodes.insert(id, Node::Transform { translation, child });
+                }
+                b"nGRP" => {
+                    let id = content.i32()?;
+                    let _attributes = content.dict()?;
+                    let count = content.u32()?;
+                    let children = (0..count).map(|_| content.i32()).collect::<Result<_, _>>()?;
+                    nodes.insert(id, Node::Group(children));
+                }
+                b"nSHP" => {
+                    let id = content.i32()?;
+                    let _attributes = content.dict()?;
+                    if content.u32()? == 0 {
+                        return Err(parse_error(format!("shape node {} has no model", id)));
+                    }
+                    // more models are animation frames, the first one is shown
+                    let model = content.u32()? as usize;
+                    nodes.insert(id, Node::Shape(model));
+                }
+                _ => {}
+            }
+        }
+
+        if !nodes.is_empty() {
+            // node 0 is the root transform
+            place(&nodes, 0, [0; 3], 0, &mut file.placements)?;
+            if let Some(placement) = file.placements.iter().find(|placement| placement.model >= file.models.len()) {
+                return Err(parse_error(format!("missing model {}", placement.model)));
+            }
+        }
+        Ok(file)
+    }
+
+    pub fn color(&self, index: u8) -> [f32; 4] {
+        let rgba = self.palette[index as usize];
+        [
+            rgba[0] as f32 / 255.,
+            rgba[1] as f32 / 255.,
+            rgba[2] as f32 / 255.,
+            rgba[3] as f32 / 255.,
+        ]
+    }
+
+    /// The models to draw and where their corner goes, in voxels, before centering.
+    fn layout(&self) -> Vec<(&VoxModel, Vector3<f32>)> {
+        if self.placements.is_empty() {
+            let mut x = 0.;
+            return self
+                .models
+                .iter()
+                .map(|model| {
+                    let corner = Vector3::new(x, -(model.size[1] as f32) / 2., -(model.size[2] as f32) / 2.);
+                    x += model.size[0] as f32 + MODEL_GAP;
+                    (model, corner)
+                })
+                .collect();
+        }
+        self.placements
+            .iter()
+            .filter_map(|placement| {
+                let model = self.models.get(placement.model)?;
+                // MagicaVoxel puts the center of the model, rounded down, at the translation
+                let corner = |axis: usize| (placement.translation[axis] - (model.size[axis] / 2) as i32) as f32;
+                Some((model, Vector3::new(corner(0), corner(1), corner(2))))
+            })
+            .collect()
+    }
+
+    /// The lowest and highest corners of the models in `layout`.
+    fn bounds(layout: &[(&VoxModel, Vector3<f32>)]) -> (Vector3<f32>, Vector3<f32>) {
+        let mut corners = layout.iter().map(|(model, corner)| {
+            let size = Vector3::new(model.size[0] as f32, model.size[1] as f32, model.size[2] as f32);
+            (*corner, corner + size)
+        });
+        let first = corners.next().unwrap_or((Vector3::new(0., 0., 0.), Vector3::new(0., 0., 0.)));
+        corners.fold(first, |(min, max), (low, high)| {
+            (
+                Vector3::new(min.x.min(low.x), min.y.min(low.y), min.z.min(low.z)),
+                Vector3::new(max.x.max(high.x), max.y.max(high.y), max.z.max(high.z)),
+            )
+        })
+    }
+
+    /// Where the corner of each model goes, in voxels, with the whole scene centered on the origin.
+    fn placed_models(&self) -> Vec<(&VoxModel, Vector3<f32>)> {
+        let mut layout = self.layout();
+        let (min, max) = Self::bounds(&layout);
+        let center = (min + max) / 2.;
+        for (_, corner) in &mut layout {
+            *corner -= center;
+        }
+        layout
+    }
+
+    /// The size of a voxel for the models to fit in a box of the unit cube's size.
+    pub fn fitting_voxel_size(&self) -> f32 {
+        let (min, max) = Self::bounds(&self.layout());
+        let extent = max - min;
+        2. / extent.x.max(extent.y).max(extent.z).max(1.)
+    }
+
+    /// A box instance per voxel, with its palette color, to draw with the unit cube.
+    pub fn box_instances(&self, voxel_size: f32) -> Vec<BoxInstance> {
+        let mut instances = Vec::new();
+        for (model, offset) in self.placed_models() {
+            for voxel in &model.voxels {
+                let center = Vector3::new(
+                    voxel.pos[0] as f32 + 0.5,
+                    voxel.pos[1] as f32 + 0.5,
+                    voxel.pos[2] as f32 + 0.5,
+                );
+                // the unit cube goes from -1 to 1
+                let transform =
+                    Matrix4::from_translation((offset + center) * voxel_size) * Matrix4::from_scale(voxel_size / 2.);
+                instances.push(BoxInstance::new(transform, self.color(voxel.color_index)));
+            }
+        }
+        instances
+    }
+
+    /// The visible faces of the voxels, greedy meshed, colored from the palette.
+    pub fn mesh(&self, voxel_size: f32) -> Mesh {
+        let mut mesh = Mesh::default();
+        for (model, offset) in self.placed_models() {
+            let model_mesh = model.grid().mesh(|color_index| self.color(color_index));
+            let first = mesh.vertices.len() as u32;
+            mesh.vertices.extend(model_mesh.vertices.into_iter().map(|mut vertex| {
+                for axis in 0..3 {
+                    vertex.pos[axis] = (vertex.pos[axis] + offset[axis]) * voxel_size;
+                }
+                vertex
+            }));
+            mesh.indices.extend(model_mesh.indices.into_iter().map(|index| first + index));
+        }
+        mesh
+    }
+}
+
+impl VoxModel {
+    /// The voxels as a grid, their color index being the material.
+    pub fn grid(&self) -> VoxelGrid {
+        let mut grid = VoxelGrid::new([self.size[0] as usize, self.size[1] as usize, self.size[2] as usize]);
+        for voxel in &self.voxels {
+            let pos = [voxel.pos[0] as usize, voxel.pos[1] as usize, voxel.pos[2] as usize];
+            if (0..3).all(|axis| pos[axis] < self.size[axis] as usize) {
+                grid.set(pos, voxel.color_index);
+            }
+        }
+        grid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(&0u32.to_le_bytes());
+        chunk.extend_from_slice(content);
+        chunk
+    }
+
+    fn words(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect()
+    }
+
+    fn dict(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut dict = words(&[entries.len() as i32]);
+        for &(key, value) in entries {
+            for string in &[key, value] {
+                dict.extend(words(&[string.len() as i32]));
+                dict.extend_from_slice(string.as_bytes());
+            }
+        }
+        dict
+    }
+
+    fn ntrn(id: i32, child: i32, translation: &str) -> Vec<u8> {
+        let content = [
+            words(&[id]),
+            dict(&[]),
+            words(&[child, -1, 0, 1]),
+            dict(&[("_t", translation)]),
+        ]
+        .concat();
+        chunk(b"nTRN", &content)
+    }
+
+    fn vox(children: &[Vec<u8>]) -> Vec<u8> {
+        let children = children.concat();
+        let mut data = b"VOX ".to_vec();
+        data.extend_from_slice(&150u32.to_le_bytes());
+        data.extend_from_slice(b"MAIN");
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        data.extend_from_slice(&children);
+        data
+    }
+
+    /// Two voxels side by side along X, with palette colors 1 and 2.
+    fn two_voxels() -> Vec<u8> {
+        let size = words(&[2, 1, 1]);
+        let mut xyzi = words(&[2]);
+        xyzi.extend_from_slice(&[0, 0, 0, 1, 1, 0, 0, 2]);
+        let mut rgba = vec![0u8; 256 * 4];
+        rgba[..8].copy_from_slice(&[255, 0, 0, 255, 0, 255, 0, 255]);
+        vox(&[chunk(b"SIZE", &size), chunk(b"XYZI", &xyzi), chunk(b"RGBA", &rgba)])
+    }
+
+    /// A 2x1x1 model at the origin and a single voxel 10 voxels away along X,
+    /// placed by a root transform, a group and a transform and shape per model.
+    fn two_models() -> Vec<u8> {
+        let mut first = words(&[2]);
+        first.extend_from_slice(&[0, 0, 0, 1, 1, 0, 0, 1]);
+        let mut second = words(&[1]);
+        second.extend_from_slice(&[0, 0, 0, 2]);
+        let shape = |id, model| chunk(b"nSHP", &[words(&[id]), dict(&[]), words(&[1, model]), dict(&[])].concat());
+        vox(&[
+            chunk(b"SIZE", &words(&[2, 1, 1])),
+            chunk(b"XYZI", &first),
+            chunk(b"SIZE", &words(&[1, 1, 1])),
+            chunk(b"XYZI", &second),
+            ntrn(0, 1, "0 0 0"),
+            chunk(b"nGRP", &[words(&[1]), dict(&[]), words(&[2, 2, 4])].concat()),
+            ntrn(2, 3, "0 0 0"),
+            shape(3, 0),
+            ntrn(4, 5, "10 0 0"),
+            shape(5, 1),
+        ])
+    }
+
+    #[test]
+    fn parse_and_draw() {
+        let file = VoxFile::parse(&two_voxels()).unwrap();
+        assert_eq!(file.models.len(), 1);
+        assert_eq!(file.models[0].size, [2, 1, 1]);
+        assert_eq!(file.models[0].voxels[1], Voxel { pos: [1, 0, 0], color_index: 2 });
+        assert_eq!(file.color(2), [0., 1., 0., 1.]);
+
+        let instances = file.box_instances(1.);
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[0].color, [1., 0., 0., 1.]);
+
+        // the shared face is hidden, the others are merged where the colors match
+        let mesh = file.mesh(1.);
+        assert_eq!(mesh.indices.len(), 10 * 6);
+    }
+
+    #[test]
+    fn scene_graph_placement() {
+        let file = VoxFile::parse(&two_models()).unwrap();
+        assert_eq!(
+            file.placements,
+            vec![
+                VoxPlacement { model: 0, translation: [0, 0, 0] },
+                VoxPlacement { model: 1, translation: [10, 0, 0] },
+            ]
+        );
+
+        // the models span -1 to 11 along X, centered on 5
+        let x: Vec<f32> = file.box_instances(1.).iter().map(|instance| instance.transform[3][0]).collect();
+        assert_eq!(x, vec![-5.5, -4.5, 5.5]);
+        assert_eq!(file.fitting_voxel_size(), 2. / 12.);
+
+        // the last shape refers to model 7 instead
+        let mut missing = two_models();
+        let len = missing.len();
+        missing[len - 8] = 7;
+        assert!(VoxFile::parse(&missing).is_err());
+    }
+
+    #[test]
+    fn default_palette_ramps() {
+        let palette = default_palette();
+        assert_eq!(palette[1], [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(palette[215], [0x00, 0x00, 0x33, 0xff]);
+        assert_eq!(palette[216], [0xee, 0x00, 0x00, 0xff]);
+        assert_eq!(palette[255], [0x11, 0x11, 0x11, 0xff]);
+    }
+}
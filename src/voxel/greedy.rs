@@ -0,0 +1,125 @@
+//! Greedy meshing: slice by slice along each axis, the visible faces of the same
+//! material are merged into the largest rectangles found row by row.
+
+use super::{Material, VoxelGrid};
+use crate::box_renderer::Vertex;
+use crate::mesh::Mesh;
+
+/// Meshes the cells from `min` to `max`, excluded. Cells outside of it still hide faces.
+pub(super) fn mesh_region(
+    grid: &VoxelGrid,
+    min: [usize; 3],
+    max: [usize; 3],
+    color: &dyn Fn(Material) -> [f32; 4],
+) -> Mesh {
+    let mut mesh = Mesh::default();
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let (width, height) = (max[u] - min[u], max[v] - min[v]);
+        // the material of each visible face in the slice, or 0
+        let mut mask = vec![0; width * height];
+
+        for &positive in &[false, true] {
+            for slice in min[axis]..max[axis] {
+                for j in 0..height {
+                    for i in 0..width {
+                        let mut pos = [0; 3];
+                        pos[axis] = slice as i32;
+                        pos[u] = (min[u] + i) as i32;
+                        pos[v] = (min[v] + j) as i32;
+                        let mut neighbor = pos;
+                        neighbor[axis] += if positive { 1 } else { -1 };
+                        let material = grid.get(pos);
+                        mask[i + j * width] = if grid.get(neighbor) == 0 { material } else { 0 };
+                    }
+                }
+
+                for j in 0..height {
+                    let mut i = 0;
+                    while i < width {
+                        let material = mask[i + j * width];
+                        if material == 0 {
+                            i += 1;
+                            continue;
+                        }
+                        let mut w = 1;
+                        while i + w < width && mask[i + w + j * width] == material {
+                            w += 1;
+                        }
+                        let mut h = 1;
+                        while j + h < height && (i..i + w).all(|k| mask[k + (j + h) * width] == material) {
+                            h += 1;
+                        }
+                        for row in j..j + h {
+                            for face in &mut mask[i + row * width..i + w + row * width] {
+                                *face = 0;
+                            }
+                        }
+
+                        let mut corner = [0.; 3];
+                        corner[axis] = slice as f32;
+                        corner[u] = (min[u] + i) as f32;
+                        corner[v] = (min[v] + j) as f32;
+                        push_quad(&mut mesh, corner, axis, positive, [w as f32, h as f32], color(material));
+                        i += w;
+                    }
+                }
+            }
+        }
+    }
+    mesh
+}
+
+/// Adds the face across `axis` of the cells from `corner`, `extent` cells wide along
+/// the two other axes, facing the positive or negative direction.
+/// Texture coordinates repeat once per cell.
+fn push_quad(mesh: &mut Mesh, corner: [f32; 3], axis: usize, positive: bool, extent: [f32; 2], color: [f32; 4]) {
+    // u x v points along the axis, so (0, 0), (1, 0), (1, 1), (0, 1) winds counter-clockwise
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut normal = [0.; 3];
+    normal[axis] = if positive { 1. } else { -1. };
+    let mut corners = [[0., 0.], [1., 0.], [1., 1.], [0., 1.]];
+    if !positive {
+        corners.reverse();
+    }
+
+    let first = mesh.vertices.len() as u32;
+    for &[cu, cv] in &corners {
+        let mut pos = corner;
+        if positive {
+            pos[axis] += 1.;
+        }
+        pos[u] += cu * extent[0];
+        pos[v] += cv * extent[1];
+        let mut vertex = Vertex::new(pos, [cu * extent[0], cv * extent[1]], normal);
+        vertex.color = color;
+        mesh.vertices.push(vertex);
+    }
+    mesh.indices
+        .extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_coplanar_faces() {
+        let mut grid = VoxelGrid::new([4, 3, 1]);
+        for x in 0..4 {
+            for y in 0..3 {
+                grid.set([x, y, 0], 1);
+            }
+        }
+        let white = |_| [1.; 4];
+        // a quad per side of the slab
+        assert_eq!(grid.mesh(white).indices.len(), 6 * 6);
+
+        // another material splits the top and bottom faces, but only adds its own side
+        grid.set([3, 2, 0], 2);
+        let mesh = grid.mesh(white);
+        let top = mesh.vertices.iter().filter(|vertex| vertex.normal == [0., 0., 1.]).count() / 4;
+        assert_eq!(top, 3);
+        assert_eq!(mesh.indices.len(), (2 * 3 + 6) * 6);
+    }
+}
@@ -0,0 +1,173 @@
+//! Voxel grids, meshed one chunk at a time so that edits only re-mesh what changed.
+
+mod greedy;
+
+use crate::mesh::Mesh;
+
+/// Side of the cubic chunks grids are meshed by, in cells.
+pub const CHUNK_SIZE: usize = 16;
+
+/// What a cell is made of, 0 being empty. Only faces of the same material get merged.
+pub type Material = u8;
+
+#[derive(Clone, Debug)]
+pub struct VoxelGrid {
+    size: [usize; 3],
+    cells: Vec<Material>,
+    /// One flag per chunk, set when its mesh is outdated.
+    dirty: Vec<bool>,
+}
+
+impl VoxelGrid {
+    /// An empty grid, with every chunk to be meshed.
+    pub fn new(size: [usize; 3]) -> Self {
+        let mut grid = VoxelGrid {
+            size,
+            cells: vec![0; size.iter().product()],
+            dirty: Vec::new(),
+        };
+        grid.dirty = vec![true; grid.chunk_count()];
+        grid
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    /// Number of chunks along each axis, the last ones being partial.
+    pub fn chunks(&self) -> [usize; 3] {
+        let mut chunks = [0; 3];
+        for (chunks, size) in chunks.iter_mut().zip(&self.size) {
+            *chunks = (size + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        }
+        chunks
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.chunks().iter().product()
+    }
+
+    fn chunk_index(&self, chunk: [usize; 3]) -> usize {
+        let chunks = self.chunks();
+        chunk[0] + chunks[0] * (chunk[1] + chunks[1] * chunk[2])
+    }
+
+    /// Empty outside of the grid.
+    pub fn get(&self, pos: [i32; 3]) -> Material {
+        let inside = (0..3).all(|axis| pos[axis] >= 0 && (pos[axis] as usize) < self.size[axis]);
+        if !inside {
+            return 0;
+        }
+        let [x, y, z] = [pos[0] as usize, pos[1] as usize, pos[2] as usize];
+        self.cells[x + self.size[0] * (y + self.size[1] * z)]
+    }
+
+    /// Marks the cell's chunk for re-meshing, along with the neighbor chunks
+    /// whose faces it may hide or reveal.
+    pub fn set(&mut self, pos: [usize; 3], material: Material) {
+        assert!((0..3).all(|axis| pos[axis] < self.size[axis]), "cell {:?} out of the grid", pos);
+        let index = pos[0] + self.size[0] * (pos[1] + self.size[1] * pos[2]);
+        if self.cells[index] == material {
+            return;
+        }
+        self.cells[index] = material;
+
+        let chunks = self.chunks();
+        let chunk = [pos[0] / CHUNK_SIZE, pos[1] / CHUNK_SIZE, pos[2] / CHUNK_SIZE];
+        let chunk_index = self.chunk_index(chunk);
+        self.dirty[chunk_index] = true;
+        for axis in 0..3 {
+            let mut neighbor = chunk;
+            if pos[axis] % CHUNK_SIZE == 0 && chunk[axis] > 0 {
+                neighbor[axis] -= 1;
+            } else if pos[axis] % CHUNK_SIZE == CHUNK_SIZE - 1 && chunk[axis] + 1 < chunks[axis] {
+                neighbor[axis] += 1;
+            } else {
+                continue;
+            }
+            let neighbor_index = self.chunk_index(neighbor);
+            self.dirty[neighbor_index] = true;
+        }
+    }
+
+    /// The chunks to re-mesh since the last call.
+    pub fn take_dirty(&mut self) -> Vec<[usize; 3]> {
+        let chunks = self.chunks();
+        let mut dirty = Vec::new();
+        for z in 0..chunks[2] {
+            for y in 0..chunks[1] {
+                for x in 0..chunks[0] {
+                    let index = self.chunk_index([x, y, z]);
+                    if std::mem::replace(&mut self.dirty[index], false) {
+                        dirty.push([x, y, z]);
+                    }
+                }
+            }
+        }
+        dirty
+    }
+
+    /// The visible faces of a chunk's cells, merged into quads, in cell units.
+    pub fn mesh_chunk(&self, chunk: [usize; 3], color: impl Fn(Material) -> [f32; 4]) -> Mesh {
+        let mut min = [0; 3];
+        let mut max = [0; 3];
+        for axis in 0..3 {
+            min[axis] = chunk[axis] * CHUNK_SIZE;
+            max[axis] = (min[axis] + CHUNK_SIZE).min(self.size[axis]);
+        }
+        greedy::mesh_region(self, min, max, &color)
+    }
+
+    /// Same as `mesh_chunk`, for the whole grid at once.
+    pub fn mesh(&self, color: impl Fn(Material) -> [f32; 4]) -> Mesh {
+        greedy::mesh_region(self, [0; 3], self.size, &color)
+    }
+}
+
+/// A mesh per chunk of a grid, kept up to date by re-meshing the chunks which changed.
+pub struct ChunkMeshes {
+    meshes: Vec<Mesh>,
+}
+
+impl ChunkMeshes {
+    pub fn new(grid: &VoxelGrid) -> Self {
+        ChunkMeshes {
+            meshes: vec![Mesh::default(); grid.chunk_count()],
+        }
+    }
+
+    /// Re-meshes the chunks changed since the last update, and returns them.
+    pub fn update(&mut self, grid: &mut VoxelGrid, color: impl Fn(Material) -> [f32; 4]) -> Vec<[usize; 3]> {
+        let dirty = grid.take_dirty();
+        for &chunk in &dirty {
+            self.meshes[grid.chunk_index(chunk)] = grid.mesh_chunk(chunk, &color);
+        }
+        dirty
+    }
+
+    pub fn get(&self, grid: &VoxelGrid, chunk: [usize; 3]) -> &Mesh {
+        &self.meshes[grid.chunk_index(chunk)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remeshes_changed_chunks() {
+        let mut grid = VoxelGrid::new([2 * CHUNK_SIZE, CHUNK_SIZE, 1]);
+        let mut meshes = ChunkMeshes::new(&grid);
+        let white = |_| [1.; 4];
+        assert_eq!(meshes.update(&mut grid, white), vec![[0, 0, 0], [1, 0, 0]]);
+        assert!(meshes.update(&mut grid, white).is_empty());
+
+        grid.set([0, 0, 0], 1);
+        assert_eq!(meshes.update(&mut grid, white), vec![[0, 0, 0]]);
+        assert_eq!(meshes.get(&grid, [0, 0, 0]).indices.len(), 6 * 6);
+
+        // on the boundary, the neighbor chunk's faces may change too
+        grid.set([CHUNK_SIZE, 0, 0], 1);
+        assert_eq!(meshes.update(&mut grid, white), vec![[0, 0, 0], [1, 0, 0]]);
+    }
+}